@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::LagomError;
+use crate::gamepad::{GamepadAxis, GamepadButton};
+use crate::input::{Button, Input, MouseButton};
+
+/// Where an axis gets its value from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held, 1 while `positive` is, 0 for both or neither.
+    Buttons { negative: Button, positive: Button },
+    /// A stick on pad `pad`, with the deadzone taken out.
+    Gamepad { pad: u32, axis: GamepadAxis },
+}
+
+/// Named actions and axes, e.g. "jump" or "move_x", each bound to any number of buttons, so game code doesn't check
+/// keys directly and players can rebind them. Names can't contain whitespace.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Button>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a button to an action, creating it if needed.
+    pub fn bind_action(&mut self, action: &str, button: Button) -> Result<(), LagomError> {
+        let buttons = self.actions.entry(check_name(action)?.to_string()).or_default();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+        Ok(())
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) -> Result<(), LagomError> {
+        let bindings = self.axes.entry(check_name(axis)?.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+        Ok(())
+    }
+
+    /// Replace everything bound to an action, e.g. after the player picks a new key.
+    pub fn rebind_action(&mut self, action: &str, buttons: Vec<Button>) -> Result<(), LagomError> {
+        self.actions.insert(check_name(action)?.to_string(), buttons);
+        Ok(())
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) -> Result<(), LagomError> {
+        self.axes.insert(check_name(axis)?.to_string(), bindings);
+        Ok(())
+    }
+
+    pub fn unbind_action(&mut self, action: &str, button: &Button) {
+        if let Some(buttons) = self.actions.get_mut(action) {
+            buttons.retain(|b| b != button);
+        }
+    }
+
+    pub fn action_bindings(&self, action: &str) -> &[Button] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map_or(&[], Vec::as_slice)
+    }
+
+    /// Any of the action's buttons is held.
+    pub fn is_down(&self, input: &Input, action: &str) -> bool {
+        self.action_bindings(action).iter().any(|button| input.is_down(button))
+    }
+
+    /// The action started this frame: one of its buttons went down and none were held before.
+    pub fn just_pressed(&self, input: &Input, action: &str) -> bool {
+        let buttons = self.action_bindings(action);
+        buttons.iter().any(|button| input.just_pressed(button))
+            && buttons.iter().all(|button| input.just_pressed(button) || !input.is_down(button))
+    }
+
+    /// The action ended this frame: one of its buttons came up and none are still held.
+    pub fn just_released(&self, input: &Input, action: &str) -> bool {
+        let buttons = self.action_bindings(action);
+        buttons.iter().any(|button| input.just_released(button)) && !buttons.iter().any(|button| input.is_down(button))
+    }
+
+    /// -1 to 1, from whichever binding is pushed furthest.
+    pub fn axis(&self, input: &Input, axis: &str) -> f32 {
+        self.axis_bindings(axis).iter()
+            .map(|binding| match binding {
+                AxisBinding::Buttons { negative, positive } => {
+                    input.is_down(positive) as i32 as f32 - input.is_down(negative) as i32 as f32
+                }
+                AxisBinding::Gamepad { pad, axis } => input.gamepad_axis(*pad, *axis),
+            })
+            .fold(0.0, |strongest: f32, value| if value.abs() > strongest.abs() { value } else { strongest })
+            .clamp(-1.0, 1.0)
+    }
+}
+
+/// One line per action or axis, e.g. `action jump Key:Space Gamepad:0:South` or
+/// `axis move_x Keys:Key:KeyA|Key:KeyD Stick:0:LeftX`. Small enough to keep in localStorage.
+impl fmt::Display for ActionMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (action, buttons) in &self.actions {
+            write!(f, "action {}", action)?;
+            for button in buttons {
+                write!(f, " {}", button)?;
+            }
+            writeln!(f)?;
+        }
+        for (axis, bindings) in &self.axes {
+            write!(f, "axis {}", axis)?;
+            for binding in bindings {
+                match binding {
+                    AxisBinding::Buttons { negative, positive } => write!(f, " Keys:{}|{}", negative, positive)?,
+                    AxisBinding::Gamepad { pad, axis } => write!(f, " Stick:{}:{}", pad, axis_name(*axis))?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ActionMap {
+    type Err = LagomError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = ActionMap::new();
+
+        for (number, line) in s.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let error = |message: &str| LagomError::InvalidBindings(format!("line {}: {}", number + 1, message));
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or_default();
+            let name = words.next().ok_or_else(|| error("missing name"))?;
+
+            match kind {
+                "action" => {
+                    let buttons = words.map(|word| word.parse::<Button>().map_err(|e| error(&e)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    map.rebind_action(name, buttons)?;
+                }
+                "axis" => {
+                    let bindings = words.map(|word| parse_axis_binding(word).map_err(|e| error(&e)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    map.rebind_axis(name, bindings)?;
+                }
+                other => return Err(error(&format!("expected action or axis, found \"{}\"", other))),
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// Names are written out space separated, so one with whitespace in it wouldn't read back.
+fn check_name(name: &str) -> Result<&str, LagomError> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(LagomError::InvalidActionName(name.to_string()));
+    }
+    Ok(name)
+}
+
+fn parse_axis_binding(word: &str) -> Result<AxisBinding, String> {
+    if let Some(keys) = word.strip_prefix("Keys:") {
+        let (negative, positive) = keys.split_once('|').ok_or_else(|| format!("expected two buttons in \"{}\"", word))?;
+        return Ok(AxisBinding::Buttons { negative: negative.parse()?, positive: positive.parse()? });
+    }
+    if let Some(stick) = word.strip_prefix("Stick:") {
+        let (pad, axis) = stick.split_once(':').ok_or_else(|| format!("expected a pad and axis in \"{}\"", word))?;
+        let pad = pad.parse().map_err(|_| format!("bad pad index in \"{}\"", word))?;
+        return Ok(AxisBinding::Gamepad { pad, axis: parse_axis(axis)? });
+    }
+    Err(format!("unknown axis binding \"{}\"", word))
+}
+
+/// `Key:Space`, `Mouse:Left` or `Gamepad:0:South`. Unnamed mouse and pad buttons are numbers.
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Button::Key(code) => write!(f, "Key:{}", code),
+            Button::Mouse(MouseButton::Other(index)) => write!(f, "Mouse:{}", index),
+            Button::Mouse(button) => write!(f, "Mouse:{:?}", button),
+            Button::Gamepad(pad, GamepadButton::Other(index)) => write!(f, "Gamepad:{}:{}", pad, index),
+            Button::Gamepad(pad, button) => write!(f, "Gamepad:{}:{:?}", pad, button),
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("unknown button \"{}\"", s);
+
+        if let Some(code) = s.strip_prefix("Key:") {
+            return Ok(Button::Key(code.to_string()));
+        }
+        if let Some(name) = s.strip_prefix("Mouse:") {
+            return match name {
+                "Left" => Ok(Button::Mouse(MouseButton::Left)),
+                "Middle" => Ok(Button::Mouse(MouseButton::Middle)),
+                "Right" => Ok(Button::Mouse(MouseButton::Right)),
+                index => index.parse().map(|index| Button::Mouse(MouseButton::Other(index))).map_err(|_| bad()),
+            };
+        }
+        if let Some(rest) = s.strip_prefix("Gamepad:") {
+            let (pad, name) = rest.split_once(':').ok_or_else(bad)?;
+            let pad = pad.parse().map_err(|_| bad())?;
+            let button = match name.parse::<u32>() {
+                Ok(index) => GamepadButton::Other(index),
+                Err(_) => (0..).map(|index| GamepadButton::from_index(index, true))
+                    .take_while(|button| !matches!(button, GamepadButton::Other(_)))
+                    .find(|button| format!("{:?}", button) == name)
+                    .ok_or_else(bad)?,
+            };
+            return Ok(Button::Gamepad(pad, button));
+        }
+        Err(bad())
+    }
+}
+
+fn axis_name(axis: GamepadAxis) -> String {
+    match axis {
+        GamepadAxis::Other(index) => index.to_string(),
+        axis => format!("{:?}", axis),
+    }
+}
+
+fn parse_axis(name: &str) -> Result<GamepadAxis, String> {
+    match name.parse::<u32>() {
+        Ok(index) => Ok(GamepadAxis::Other(index)),
+        Err(_) => (0..4).map(|index| GamepadAxis::from_index(index, true))
+            .find(|axis| axis_name(*axis) == name)
+            .ok_or_else(|| format!("unknown axis \"{}\"", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actions::{ActionMap, AxisBinding};
+    use crate::error::LagomError;
+    use crate::gamepad::{GamepadAxis, GamepadButton};
+    use crate::input::{Button, Input, InputEvent, MouseButton};
+    use crate::scaling::{ScalePolicy, Screen};
+
+    fn screen() -> Screen {
+        Screen::new(ScalePolicy::Fixed, 320, 240)
+    }
+
+    fn map() -> ActionMap {
+        let mut map = ActionMap::new();
+        map.bind_action("jump", Button::key("Space")).unwrap();
+        map.bind_action("jump", Button::Gamepad(0, GamepadButton::South)).unwrap();
+        map.bind_action("fire", Button::Mouse(MouseButton::Left)).unwrap();
+        map.bind_axis("move_x", AxisBinding::Buttons { negative: Button::key("KeyA"), positive: Button::key("KeyD") })
+            .unwrap();
+        map.bind_axis("move_x", AxisBinding::Gamepad { pad: 0, axis: GamepadAxis::LeftX }).unwrap();
+        map
+    }
+
+    #[test]
+    fn actions_follow_any_bound_button() {
+        let map = map();
+        let mut input = Input::default();
+
+        input.process(vec![InputEvent::ButtonDown(Button::key("Space"))], &screen());
+        assert!(map.is_down(&input, "jump") && map.just_pressed(&input, "jump"));
+        assert!(!map.is_down(&input, "fire"));
+        assert!(!map.is_down(&input, "missing"));
+
+        input.process(vec![InputEvent::ButtonUp(Button::key("Space"))], &screen());
+        assert!(map.just_released(&input, "jump"));
+    }
+
+    #[test]
+    fn second_button_on_a_held_action_isnt_a_new_press() {
+        let map = map();
+        let mut input = Input::default();
+        input.process(vec![InputEvent::ButtonDown(Button::key("Space"))], &screen());
+        input.process(vec![InputEvent::ButtonDown(Button::Gamepad(0, GamepadButton::South))], &screen());
+        assert!(map.is_down(&input, "jump"));
+        assert!(!map.just_pressed(&input, "jump"));
+    }
+
+    #[test]
+    fn axes_combine_keys() {
+        let map = map();
+        let mut input = Input::default();
+        input.process(vec![InputEvent::ButtonDown(Button::key("KeyA"))], &screen());
+        assert_eq!(map.axis(&input, "move_x"), -1.0);
+        input.process(vec![InputEvent::ButtonDown(Button::key("KeyD"))], &screen());
+        assert_eq!(map.axis(&input, "move_x"), 0.0);
+    }
+
+    #[test]
+    fn rebinding_replaces_buttons() {
+        let mut map = map();
+        map.rebind_action("jump", vec![Button::key("KeyW")]).unwrap();
+        assert_eq!(map.action_bindings("jump"), &[Button::key("KeyW")]);
+        map.unbind_action("jump", &Button::key("KeyW"));
+        assert!(map.action_bindings("jump").is_empty());
+    }
+
+    #[test]
+    fn bindings_round_trip_through_a_string() {
+        let mut map = map();
+        map.bind_action("menu", Button::Gamepad(1, GamepadButton::Other(20))).unwrap();
+        map.bind_action("menu", Button::Mouse(MouseButton::Other(4))).unwrap();
+        map.bind_axis("look_y", AxisBinding::Gamepad { pad: 1, axis: GamepadAxis::Other(5) }).unwrap();
+
+        let saved = map.to_string();
+        assert!(saved.contains("action jump Key:Space Gamepad:0:South\n"));
+        assert_eq!(saved.parse::<ActionMap>(), Ok(map));
+    }
+
+    #[test]
+    fn bad_bindings_are_errors() {
+        assert!("action jump Key:Space Joystick:1".parse::<ActionMap>().is_err());
+        assert!("button jump Key:Space".parse::<ActionMap>().is_err());
+        assert!("axis move_x Keys:KeyA".parse::<ActionMap>().is_err());
+        assert_eq!("\n\n".parse::<ActionMap>(), Ok(ActionMap::new()));
+    }
+
+    #[test]
+    fn names_with_whitespace_are_rejected() {
+        let mut map = ActionMap::new();
+        assert_eq!(map.bind_action("jump high", Button::key("Space")),
+                   Err(LagomError::InvalidActionName("jump high".to_string())));
+        assert!(map.bind_axis("", AxisBinding::Gamepad { pad: 0, axis: GamepadAxis::LeftX }).is_err());
+        assert!(map.rebind_action("fire\n", vec![]).is_err());
+        assert_eq!(map, ActionMap::new());
+    }
+}
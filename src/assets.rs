@@ -0,0 +1,622 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{RequestCache, RequestInit, Response};
+
+use crate::audio::SoundId;
+use crate::error::LagomError;
+use crate::hot_reload::{HotReload, matches_path, Watcher};
+use crate::text::FontId;
+
+/// Refers to an asset for as long as the `AssetServer` lives, whether it has loaded yet or not.
+pub type Handle = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetKind {
+    /// PNG, JPEG or anything else the `image` crate reads, becomes a texture. Decoded on the main thread, one a frame,
+    /// so very large images still make for one slow frame.
+    Image,
+    /// WAV, OGG or anything else the browser decodes.
+    Sound,
+    /// TrueType or OpenType, rasterised at `size` pixels high.
+    Font { size: f32 },
+    /// Raw bytes for the game to make sense of, e.g. a level.
+    Data,
+    /// A list of other assets to load, see `AssetServer::load_manifest`.
+    Manifest,
+}
+
+/// What an asset turned into once loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Asset {
+    Texture(u32),
+    Sound(SoundId),
+    Font(FontId),
+    Data(Vec<u8>),
+    Manifest(Vec<Handle>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+enum Stage {
+    Fetching,
+    /// Downloaded, waiting for its turn to be decoded.
+    Fetched(Vec<u8>),
+    /// Handed to something that finishes in the background, e.g. the browser decoding a sound.
+    Decoding(Asset),
+    Ready(Asset),
+    Failed(String),
+}
+
+struct Entry {
+    path: String,
+    kind: AssetKind,
+    /// From a manifest.
+    name: Option<String>,
+    stage: Stage,
+    /// The server's `ETag` or `Last-Modified` for what we have, to tell when it changed.
+    version: Option<String>,
+    /// Being downloaded again while the current version stays in use.
+    reloading: bool,
+    /// A new version waiting to be decoded in place of the current one.
+    reloaded: Option<Vec<u8>>,
+}
+
+/// A finished download: the bytes and their version.
+type Download = Result<(Vec<u8>, Option<String>), String>;
+
+/// Downloads finish here, a frame at a time they're picked up by `AssetServer::receive`.
+type Arrivals = Rc<RefCell<Vec<(Handle, Download)>>>;
+
+/// Fetches assets by URL in the background. Loading returns a handle straight away, and each frame the game decodes
+/// a few of whatever has arrived so a big batch doesn't stall a frame.
+pub struct AssetServer {
+    entries: Vec<Entry>,
+    arrived: Arrivals,
+    watcher: Option<Watcher>,
+}
+
+impl Default for AssetServer {
+    fn default() -> Self {
+        Self { entries: Vec::new(), arrived: Rc::new(RefCell::new(Vec::new())), watcher: None }
+    }
+}
+
+impl AssetServer {
+    pub fn load_image(&mut self, path: &str) -> Handle {
+        self.load(path, AssetKind::Image, None)
+    }
+
+    pub fn load_sound(&mut self, path: &str) -> Handle {
+        self.load(path, AssetKind::Sound, None)
+    }
+
+    pub fn load_font(&mut self, path: &str, size: f32) -> Handle {
+        self.load(path, AssetKind::Font { size }, None)
+    }
+
+    pub fn load_data(&mut self, path: &str) -> Handle {
+        self.load(path, AssetKind::Data, None)
+    }
+
+    /// Load a text file listing assets, one per line as `kind name path`, with a size after fonts. Kinds are
+    /// `image`, `sound`, `font` and `data`, paths are relative to the manifest and `#` starts a comment:
+    ///
+    /// ```text
+    /// image player sprites/player.png
+    /// font ui fonts/ui.ttf 24
+    /// ```
+    ///
+    /// The manifest's state stays `Loading` until everything in it has finished. Entries can be looked up by name
+    /// with `handle`.
+    pub fn load_manifest(&mut self, path: &str) -> Handle {
+        self.load(path, AssetKind::Manifest, None)
+    }
+
+    /// Loading the same path as the same kind again gives back the same handle.
+    fn load(&mut self, path: &str, kind: AssetKind, name: Option<String>) -> Handle {
+        let (handle, new) = self.add(path, kind, name);
+        if new {
+            fetch(handle, path.to_string(), false, self.arrived.clone());
+        }
+        handle
+    }
+
+    /// Watch for asset files changing and load them again, replacing them in place so handles, texture IDs and so
+    /// on stay the same. For development, the loaded assets are checked again and again.
+    pub fn enable_hot_reload(&mut self, mode: HotReload) -> Result<(), LagomError> {
+        self.watcher = Some(Watcher::new(mode)?);
+        Ok(())
+    }
+
+    pub fn disable_hot_reload(&mut self) {
+        self.watcher = None;
+    }
+
+    /// Download an asset again. A loaded one keeps being used until the new version is decoded, a failed one is
+    /// tried again from scratch.
+    pub fn reload(&mut self, handle: Handle) {
+        if self.start_reload(handle) {
+            fetch(handle, self.entries[handle].path.clone(), true, self.arrived.clone());
+        }
+    }
+
+    /// Returns whether a download needs starting.
+    fn start_reload(&mut self, handle: Handle) -> bool {
+        let entry = match self.entries.get_mut(handle) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        match entry.stage {
+            Stage::Ready(_) if !entry.reloading => {
+                entry.reloading = true;
+                true
+            }
+            Stage::Failed(_) => {
+                entry.stage = Stage::Fetching;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reload everything loaded from `path`.
+    pub fn reload_path(&mut self, path: &str) {
+        let handles: Vec<Handle> = (0..self.entries.len())
+            .filter(|handle| matches_path(&self.entries[*handle].path, path))
+            .collect();
+        for handle in handles {
+            self.reload(handle);
+        }
+    }
+
+    /// The server reported `version` for an asset. Reload it if that isn't what we have.
+    fn check_version(&mut self, handle: Handle, version: String) {
+        match &self.entries[handle].version {
+            Some(current) if *current != version => self.reload(handle),
+            Some(_) => {}
+            None => self.entries[handle].version = Some(version),
+        }
+    }
+
+    /// Returns the handle and whether it's new.
+    fn add(&mut self, path: &str, kind: AssetKind, name: Option<String>) -> (Handle, bool) {
+        if let Some(handle) = self.entries.iter().position(|entry| entry.path == path && entry.kind == kind) {
+            if name.is_some() {
+                self.entries[handle].name = name;
+            }
+            return (handle, false);
+        }
+        self.entries.push(Entry {
+            path: path.to_string(),
+            kind,
+            name,
+            stage: Stage::Fetching,
+            version: None,
+            reloading: false,
+            reloaded: None,
+        });
+        (self.entries.len() - 1, true)
+    }
+
+    /// An asset by its manifest name, or the path it was loaded from.
+    pub fn handle(&self, name: &str) -> Option<Handle> {
+        self.entries.iter().position(|entry| entry.name.as_deref() == Some(name))
+            .or_else(|| self.entries.iter().position(|entry| entry.path == name))
+    }
+
+    pub fn path(&self, handle: Handle) -> Option<&str> {
+        self.entries.get(handle).map(|entry| entry.path.as_str())
+    }
+
+    pub fn state(&self, handle: Handle) -> LoadState {
+        match self.entries.get(handle).map(|entry| &entry.stage) {
+            None => LoadState::Failed(format!("no asset with handle {}", handle)),
+            Some(Stage::Ready(Asset::Manifest(handles))) => {
+                if handles.iter().any(|handle| self.state(*handle) == LoadState::Loading) {
+                    LoadState::Loading
+                } else {
+                    LoadState::Loaded
+                }
+            }
+            Some(Stage::Ready(_)) => LoadState::Loaded,
+            Some(Stage::Failed(message)) => LoadState::Failed(message.clone()),
+            Some(_) => LoadState::Loading,
+        }
+    }
+
+    /// Loaded, or failed so there's nothing more to wait for.
+    pub fn is_done(&self, handle: Handle) -> bool {
+        self.state(handle) != LoadState::Loading
+    }
+
+    /// How much of everything asked for so far has finished, 0 to 1.
+    pub fn progress(&self) -> f32 {
+        let assets = self.entries.iter().filter(|entry| entry.kind != AssetKind::Manifest);
+        let (done, total) = assets.fold((0, 0), |(done, total), entry| {
+            let finished = matches!(entry.stage, Stage::Ready(_) | Stage::Failed(_));
+            (done + finished as usize, total + 1)
+        });
+        if total == 0 { 1.0 } else { done as f32 / total as f32 }
+    }
+
+    pub fn asset(&self, handle: Handle) -> Option<&Asset> {
+        match self.entries.get(handle).map(|entry| &entry.stage) {
+            Some(Stage::Ready(asset)) => Some(asset),
+            _ => None,
+        }
+    }
+
+    pub fn texture(&self, handle: Handle) -> Option<u32> {
+        match self.asset(handle) {
+            Some(Asset::Texture(texture)) => Some(*texture),
+            _ => None,
+        }
+    }
+
+    pub fn sound(&self, handle: Handle) -> Option<SoundId> {
+        match self.asset(handle) {
+            Some(Asset::Sound(sound)) => Some(*sound),
+            _ => None,
+        }
+    }
+
+    pub fn font(&self, handle: Handle) -> Option<FontId> {
+        match self.asset(handle) {
+            Some(Asset::Font(font)) => Some(*font),
+            _ => None,
+        }
+    }
+
+    pub fn data(&self, handle: Handle) -> Option<&[u8]> {
+        match self.asset(handle) {
+            Some(Asset::Data(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Data as UTF-8 text.
+    pub fn text(&self, handle: Handle) -> Option<&str> {
+        self.data(handle).and_then(|data| std::str::from_utf8(data).ok())
+    }
+
+    /// Pick up finished downloads and changes from hot reloading. Manifests are read straight away and start loading
+    /// what they list. Returns reloads that failed, the old version is kept for those.
+    pub fn receive(&mut self) -> Vec<LagomError> {
+        let mut errors = Vec::new();
+
+        if let Some(watcher) = &self.watcher {
+            let (versions, changed) = (watcher.take_versions(), watcher.take_changed());
+            for (handle, version) in versions {
+                self.check_version(handle, version);
+            }
+            for path in changed {
+                self.reload_path(&path);
+            }
+        }
+
+        let arrived: Vec<_> = self.arrived.borrow_mut().drain(..).collect();
+        for (handle, result) in arrived {
+            let reloading = std::mem::take(&mut self.entries[handle].reloading);
+            let (bytes, version) = match result {
+                Ok(download) => download,
+                Err(message) if reloading => {
+                    errors.push(LagomError::AssetFetch(message));
+                    continue;
+                }
+                Err(message) => {
+                    self.entries[handle].stage = Stage::Failed(message);
+                    continue;
+                }
+            };
+
+            if version.is_some() {
+                self.entries[handle].version = version;
+            }
+            if self.entries[handle].kind == AssetKind::Manifest {
+                match self.read_manifest(handle, &bytes) {
+                    Ok(handles) => self.entries[handle].stage = Stage::Ready(Asset::Manifest(handles)),
+                    Err(e) if reloading => errors.push(e),
+                    Err(e) => self.entries[handle].stage = Stage::Failed(e.to_string()),
+                }
+            } else if reloading {
+                self.entries[handle].reloaded = Some(bytes);
+            } else {
+                self.entries[handle].stage = Stage::Fetched(bytes);
+            }
+        }
+
+        let watched = self.watched();
+        if let Some(watcher) = &mut self.watcher {
+            watcher.poll(watched);
+        }
+
+        errors
+    }
+
+    /// Assets polling looks for new versions of. Failed ones are included so a file that was read half written is
+    /// loaded again once it's finished.
+    fn watched(&self) -> Vec<(Handle, String)> {
+        self.entries.iter().enumerate()
+            .filter(|(_, entry)| matches!(entry.stage, Stage::Ready(_) | Stage::Failed(_)))
+            .map(|(handle, entry)| (handle, entry.path.clone()))
+            .collect()
+    }
+
+    fn read_manifest(&mut self, handle: Handle, bytes: &[u8]) -> Result<Vec<Handle>, LagomError> {
+        let manifest_path = self.entries[handle].path.clone();
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| LagomError::AssetDecode(format!("{} isn't UTF-8 text", manifest_path)))?;
+
+        parse_manifest(text)
+            .map_err(|e| LagomError::AssetDecode(format!("{}: {}", manifest_path, e)))?
+            .into_iter()
+            .map(|(kind, name, path)| Ok(self.load(&resolve(&manifest_path, &path), kind, Some(name))))
+            .collect()
+    }
+
+    /// The next downloaded asset to decode, handing over its bytes.
+    pub fn next_fetched(&mut self) -> Option<(Handle, AssetKind, Vec<u8>)> {
+        let handle = self.entries.iter().position(|entry| matches!(entry.stage, Stage::Fetched(_)))?;
+        let entry = &mut self.entries[handle];
+        match std::mem::replace(&mut entry.stage, Stage::Fetching) {
+            Stage::Fetched(bytes) => Some((handle, entry.kind.clone(), bytes)),
+            _ => None,
+        }
+    }
+
+    /// The next asset with a new version downloaded, with what it is now and the new bytes to replace it with.
+    pub fn next_reloaded(&mut self) -> Option<(Handle, Asset, Vec<u8>)> {
+        let handle = self.entries.iter().position(|entry| entry.reloaded.is_some())?;
+        let entry = &mut self.entries[handle];
+        match (&entry.stage, entry.reloaded.take()) {
+            (Stage::Ready(asset), Some(bytes)) => Some((handle, asset.clone(), bytes)),
+            _ => None,
+        }
+    }
+
+    /// Store what an asset decoded to, or why it couldn't be.
+    pub fn finish(&mut self, handle: Handle, result: Result<Asset, LagomError>) {
+        self.entries[handle].stage = match result {
+            Ok(asset) => Stage::Ready(asset),
+            Err(e) => Stage::Failed(e.to_string()),
+        };
+    }
+
+    /// Decoded, but something else is still working on it. Check back with `decoding` and `finish` it when done.
+    pub fn finish_later(&mut self, handle: Handle, asset: Asset) {
+        self.entries[handle].stage = Stage::Decoding(asset);
+    }
+
+    pub fn decoding(&self) -> Vec<(Handle, Asset)> {
+        self.entries.iter().enumerate()
+            .filter_map(|(handle, entry)| match &entry.stage {
+                Stage::Decoding(asset) => Some((handle, asset.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// `(kind, name, path)` for each line.
+fn parse_manifest(text: &str) -> Result<Vec<(AssetKind, String, String)>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let kind = match (words.first().copied(), words.len()) {
+                (Some("image"), 3) => AssetKind::Image,
+                (Some("sound"), 3) => AssetKind::Sound,
+                (Some("data"), 3) => AssetKind::Data,
+                (Some("font"), 4) => AssetKind::Font {
+                    size: words[3].parse().map_err(|_| format!("line {}: bad font size \"{}\"", number, words[3]))?,
+                },
+                _ => return Err(format!("line {}: expected `kind name path`, found \"{}\"", number, line)),
+            };
+            Ok((kind, words[1].to_string(), words[2].to_string()))
+        })
+        .collect()
+}
+
+/// `path` relative to the directory `base` is in, unless it's absolute.
+fn resolve(base: &str, path: &str) -> String {
+    if path.starts_with('/') || path.contains("://") {
+        return path.to_string();
+    }
+    match base.rfind('/') {
+        Some(index) => format!("{}{}", &base[..=index], path),
+        None => path.to_string(),
+    }
+}
+
+fn fetch(handle: Handle, url: String, reload: bool, arrived: Arrivals) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = fetch_bytes(&url, reload).await.map_err(|e| format!("couldn't fetch {}: {}", url, e));
+        arrived.borrow_mut().push((handle, result));
+    });
+}
+
+fn js_message(e: wasm_bindgen::JsValue) -> String {
+    e.as_string().unwrap_or_else(|| format!("{:?}", e))
+}
+
+/// `fresh` skips the browser's cache, to see changes.
+async fn request(url: &str, method: &str, fresh: bool) -> Result<Response, String> {
+    let window = web_sys::window().ok_or_else(|| String::from("no window"))?;
+    let init = RequestInit::new();
+    init.set_method(method);
+    if fresh {
+        init.set_cache(RequestCache::NoStore);
+    }
+
+    let response: Response = JsFuture::from(window.fetch_with_str_and_init(url, &init)).await.map_err(js_message)?
+        .dyn_into().map_err(js_message)?;
+    if !response.ok() {
+        return Err(format!("{} {}", response.status(), response.status_text()));
+    }
+    Ok(response)
+}
+
+/// The `ETag` or else `Last-Modified` header, whichever the server sends.
+fn version(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    ["ETag", "Last-Modified"].iter().find_map(|name| headers.get(name).ok().flatten())
+}
+
+/// The file's contents and version.
+pub async fn fetch_bytes(url: &str, fresh: bool) -> Result<(Vec<u8>, Option<String>), String> {
+    let response = request(url, "GET", fresh).await?;
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_message)?).await.map_err(js_message)?;
+    Ok((js_sys::Uint8Array::new(&buffer).to_vec(), version(&response)))
+}
+
+/// Just the file's version, without downloading it.
+pub async fn fetch_version(url: &str) -> Result<Option<String>, String> {
+    request(url, "HEAD", true).await.map(|response| version(&response))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assets::{Asset, AssetKind, AssetServer, LoadState, parse_manifest, resolve};
+    use crate::error::LagomError;
+
+    #[test]
+    fn manifests_are_parsed() {
+        let manifest = "# sprites\nimage player sprites/player.png\n\nfont ui ui.ttf 24 # big\ndata level levels/1.txt\n";
+        assert_eq!(parse_manifest(manifest), Ok(vec![
+            (AssetKind::Image, String::from("player"), String::from("sprites/player.png")),
+            (AssetKind::Font { size: 24.0 }, String::from("ui"), String::from("ui.ttf")),
+            (AssetKind::Data, String::from("level"), String::from("levels/1.txt")),
+        ]));
+        assert!(parse_manifest("image player").is_err());
+        assert!(parse_manifest("font ui ui.ttf big").is_err());
+        assert!(parse_manifest("video intro intro.mp4").is_err());
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_manifest() {
+        assert_eq!(resolve("assets/manifest.txt", "player.png"), "assets/player.png");
+        assert_eq!(resolve("manifest.txt", "player.png"), "player.png");
+        assert_eq!(resolve("assets/manifest.txt", "/shared/player.png"), "/shared/player.png");
+        assert_eq!(resolve("assets/manifest.txt", "https://cdn.example.com/a.png"), "https://cdn.example.com/a.png");
+    }
+
+    #[test]
+    fn assets_go_from_loading_to_loaded() {
+        let mut assets = AssetServer::default();
+        let (image, _) = assets.add("a.png", AssetKind::Image, None);
+        let (data, _) = assets.add("b.txt", AssetKind::Data, None);
+        assert_eq!(assets.add("a.png", AssetKind::Image, None), (image, false));
+        assert_eq!(assets.progress(), 0.0);
+
+        assets.arrived.borrow_mut().push((image, Ok((vec![1, 2, 3], None))));
+        assets.arrived.borrow_mut().push((data, Err(String::from("404 Not Found"))));
+        assets.receive();
+        assert_eq!(assets.state(data), LoadState::Failed(String::from("404 Not Found")));
+        assert_eq!(assets.progress(), 0.5);
+
+        let (handle, kind, bytes) = assets.next_fetched().unwrap();
+        assert_eq!((handle, kind, bytes), (image, AssetKind::Image, vec![1, 2, 3]));
+        assert!(assets.next_fetched().is_none());
+        assert_eq!(assets.state(image), LoadState::Loading);
+
+        assets.finish(image, Ok(Asset::Texture(4)));
+        assert_eq!(assets.state(image), LoadState::Loaded);
+        assert_eq!(assets.texture(image), Some(4));
+        assert_eq!(assets.sound(image), None);
+        assert_eq!(assets.progress(), 1.0);
+        assert_eq!(assets.handle("a.png"), Some(image));
+    }
+
+    #[test]
+    fn decode_failures_are_kept() {
+        let mut assets = AssetServer::default();
+        let (handle, _) = assets.add("a.png", AssetKind::Image, None);
+        assets.finish(handle, Err(LagomError::AssetDecode(String::from("bad PNG"))));
+        assert!(assets.is_done(handle));
+        assert!(matches!(assets.state(handle), LoadState::Failed(message) if message.contains("bad PNG")));
+    }
+
+    #[test]
+    fn manifest_waits_for_its_entries() {
+        let mut assets = AssetServer::default();
+        let (manifest, _) = assets.add("assets/manifest.txt", AssetKind::Manifest, None);
+        let (player, _) = assets.add("assets/player.png", AssetKind::Image, Some(String::from("player")));
+        assets.entries[manifest].stage = super::Stage::Ready(Asset::Manifest(vec![player]));
+
+        assert_eq!(assets.handle("player"), Some(player));
+        assert_eq!(assets.state(manifest), LoadState::Loading);
+        assets.finish(player, Ok(Asset::Texture(0)));
+        assert_eq!(assets.state(manifest), LoadState::Loaded);
+    }
+
+    #[test]
+    fn sounds_can_finish_later() {
+        let mut assets = AssetServer::default();
+        let (handle, _) = assets.add("jump.wav", AssetKind::Sound, None);
+        assets.finish_later(handle, Asset::Sound(2));
+        assert_eq!(assets.decoding(), vec![(handle, Asset::Sound(2))]);
+        assert_eq!(assets.state(handle), LoadState::Loading);
+        assets.finish(handle, Ok(Asset::Sound(2)));
+        assert!(assets.decoding().is_empty());
+        assert_eq!(assets.sound(handle), Some(2));
+    }
+
+    #[test]
+    fn reloads_replace_in_place() {
+        let mut assets = AssetServer::default();
+        let (handle, _) = assets.add("a.png", AssetKind::Image, None);
+        assets.arrived.borrow_mut().push((handle, Ok((vec![1], Some(String::from("v1"))))));
+        assets.receive();
+        let _ = assets.next_fetched();
+        assets.finish(handle, Ok(Asset::Texture(3)));
+
+        // Same version, nothing to do.
+        assets.check_version(handle, String::from("v1"));
+        assert!(!assets.entries[handle].reloading);
+
+        assert!(assets.start_reload(handle));
+        assert!(!assets.start_reload(handle), "already reloading");
+        assets.arrived.borrow_mut().push((handle, Ok((vec![2], Some(String::from("v2"))))));
+        assert!(assets.receive().is_empty());
+
+        // The old texture stays in use until the new bytes are decoded into it.
+        assert_eq!(assets.texture(handle), Some(3));
+        assert_eq!(assets.next_reloaded(), Some((handle, Asset::Texture(3), vec![2])));
+        assert_eq!(assets.next_reloaded(), None);
+        assert_eq!(assets.entries[handle].version.as_deref(), Some("v2"));
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_old_version() {
+        let mut assets = AssetServer::default();
+        let (handle, _) = assets.add("a.txt", AssetKind::Data, None);
+        assets.finish(handle, Ok(Asset::Data(vec![1])));
+
+        assert!(assets.start_reload(handle));
+        assets.arrived.borrow_mut().push((handle, Err(String::from("404 Not Found"))));
+        assert_eq!(assets.receive().len(), 1);
+        assert_eq!(assets.data(handle), Some(&[1][..]));
+        assert_eq!(assets.state(handle), LoadState::Loaded);
+    }
+
+    #[test]
+    fn failed_assets_are_retried() {
+        let mut assets = AssetServer::default();
+        let (handle, _) = assets.add("a.png", AssetKind::Image, None);
+        assets.finish(handle, Err(LagomError::AssetDecode(String::from("half written"))));
+        assert_eq!(assets.watched(), vec![(handle, String::from("a.png"))]);
+        assert!(assets.start_reload(handle));
+        assert!(assets.watched().is_empty());
+        assert_eq!(assets.state(handle), LoadState::Loading);
+    }
+}
@@ -0,0 +1,371 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioContextState, AudioScheduledSourceNode, console, GainNode, StereoPannerNode};
+
+use crate::error::LagomError;
+use crate::input::EventListener;
+use crate::sfxr::{SAMPLE_RATE, SfxParams};
+
+pub type SoundId = usize;
+pub type VoiceId = u32;
+
+/// Mixer channels, each with its own volume under the master volume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Music,
+    Sfx,
+}
+
+/// How to play a sound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayParams {
+    /// 0 to 1 on top of the bus volume.
+    pub volume: f32,
+    /// Playback speed, 2 is an octave up and twice as fast.
+    pub pitch: f32,
+    /// -1 is fully left, 1 fully right.
+    pub pan: f32,
+    /// Keep playing until stopped.
+    pub looping: bool,
+    pub bus: Bus,
+}
+
+impl Default for PlayParams {
+    fn default() -> Self {
+        Self { volume: 1.0, pitch: 1.0, pan: 0.0, looping: false, bus: Bus::Sfx }
+    }
+}
+
+impl PlayParams {
+    /// Looping on the music bus.
+    pub fn music() -> Self {
+        Self { looping: true, bus: Bus::Music, ..Self::default() }
+    }
+
+    /// The values Web Audio will accept, as volume, pitch, pan.
+    fn clamped(&self) -> (f32, f32, f32) {
+        (self.volume.max(0.0), self.pitch.clamp(1.0 / 16.0, 16.0), self.pan.clamp(-1.0, 1.0))
+    }
+}
+
+/// Volumes for the master output and each bus. Kept separately from the gain nodes so muting doesn't lose the
+/// volume it goes back to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mixer {
+    master: f32,
+    muted: bool,
+    music: f32,
+    sfx: f32,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self { master: 1.0, muted: false, music: 1.0, sfx: 1.0 }
+    }
+}
+
+impl Mixer {
+    /// What the master gain node should be set to.
+    pub fn master_gain(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master }
+    }
+
+    pub fn bus_volume(&self, bus: Bus) -> f32 {
+        match bus {
+            Bus::Music => self.music,
+            Bus::Sfx => self.sfx,
+        }
+    }
+
+    fn set_bus_volume(&mut self, bus: Bus, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        match bus {
+            Bus::Music => self.music = volume,
+            Bus::Sfx => self.sfx = volume,
+        }
+    }
+}
+
+enum SoundState {
+    /// The browser is still decoding it.
+    Loading,
+    Ready(AudioBuffer),
+    Failed,
+}
+
+/// The nodes of a sound that's playing.
+struct Voice {
+    source: AudioScheduledSourceNode,
+    gain: GainNode,
+}
+
+/// Sound through Web Audio. Everything plays through a gain node per bus into a master gain node.
+///
+/// Browsers keep audio suspended until the player interacts with the page, so it is resumed on the first click,
+/// touch or key press. One-shot sounds played before then are dropped, loops start once audio is unlocked.
+pub struct Audio {
+    context: AudioContext,
+    master: GainNode,
+    music: GainNode,
+    sfx: GainNode,
+    mixer: Mixer,
+    sounds: Rc<RefCell<Vec<SoundState>>>,
+    voices: Rc<RefCell<HashMap<VoiceId, Voice>>>,
+    next_voice: Cell<VoiceId>,
+    /// Removed from the window when audio is dropped.
+    _unlock_listeners: Vec<EventListener>,
+}
+
+fn audio_error(what: &str, e: JsValue) -> LagomError {
+    LagomError::Audio(format!("{}: {}", what, e.as_string().unwrap_or_else(|| format!("{:?}", e))))
+}
+
+impl Audio {
+    pub fn new() -> Result<Self, LagomError> {
+        let context = AudioContext::new().map_err(|e| audio_error("couldn't create an AudioContext", e))?;
+        let gain = || context.create_gain().map_err(|e| audio_error("couldn't create a gain node", e));
+        let (master, music, sfx) = (gain()?, gain()?, gain()?);
+
+        master.connect_with_audio_node(&context.destination()).map_err(|e| audio_error("couldn't connect master", e))?;
+        for bus in [&music, &sfx].iter() {
+            bus.connect_with_audio_node(&master).map_err(|e| audio_error("couldn't connect bus", e))?;
+        }
+
+        let mut unlock_listeners = Vec::new();
+        if let Some(window) = web_sys::window() {
+            for name in ["pointerdown", "keydown", "touchend"].iter() {
+                let context = context.clone();
+                unlock_listeners.push(EventListener::new(window.as_ref(), name, move |_| {
+                    if context.state() == AudioContextState::Suspended {
+                        let _ = context.resume();
+                    }
+                }));
+            }
+        }
+
+        Ok(Self {
+            context,
+            master,
+            music,
+            sfx,
+            mixer: Mixer::default(),
+            sounds: Rc::new(RefCell::new(Vec::new())),
+            voices: Rc::new(RefCell::new(HashMap::new())),
+            next_voice: Cell::new(0),
+            _unlock_listeners: unlock_listeners,
+        })
+    }
+
+    /// Whether the player has interacted with the page yet, so sound can be heard.
+    pub fn is_unlocked(&self) -> bool {
+        self.context.state() == AudioContextState::Running
+    }
+
+    /// Decode a WAV, OGG or anything else the browser understands, e.g. from `include_bytes!`. Decoding happens in
+    /// the background, the sound can't be played until `is_loaded`.
+    pub fn load_sound(&mut self, bytes: &[u8]) -> Result<SoundId, LagomError> {
+        let id = self.sounds.borrow().len();
+        self.sounds.borrow_mut().push(SoundState::Loading);
+        self.decode(id, bytes, false)?;
+        Ok(id)
+    }
+
+    /// Decode a new version of a sound under the same ID. The old one keeps playing until the new one is ready, and
+    /// is kept if it can't be decoded. Voices already playing it carry on.
+    pub fn replace_sound(&mut self, sound: SoundId, bytes: &[u8]) -> Result<(), LagomError> {
+        if sound >= self.sounds.borrow().len() {
+            return Err(LagomError::Audio(format!("no sound {}", sound)));
+        }
+        self.decode(sound, bytes, true)
+    }
+
+    /// Decode into the sound slot `id`, in the background. When `replacing`, a failure leaves the slot as it was.
+    fn decode(&mut self, id: SoundId, bytes: &[u8], replacing: bool) -> Result<(), LagomError> {
+        let data = js_sys::Uint8Array::from(bytes).buffer();
+        let sounds = self.sounds.clone();
+        let on_decoded = Closure::once_into_js(move |buffer: AudioBuffer| {
+            sounds.borrow_mut()[id] = SoundState::Ready(buffer);
+        });
+        let sounds = self.sounds.clone();
+        let on_error = Closure::once_into_js(move |e: JsValue| {
+            console::error_1(&format!("Couldn't decode sound {}: {:?}", id, e).into());
+            if !replacing {
+                sounds.borrow_mut()[id] = SoundState::Failed;
+            }
+        });
+
+        if let Err(e) = self.context
+            .decode_audio_data_with_success_callback_and_error_callback(&data, on_decoded.unchecked_ref(), on_error.unchecked_ref()) {
+            if !replacing {
+                self.sounds.borrow_mut()[id] = SoundState::Failed;
+            }
+            return Err(audio_error("couldn't decode sound", e));
+        }
+        Ok(())
+    }
+
+    /// A mono sound from raw samples between -1 and 1, e.g. generated ones. Ready to play straight away.
+    pub fn load_samples(&mut self, samples: &[f32], sample_rate: u32) -> Result<SoundId, LagomError> {
+        if samples.is_empty() {
+            return Err(LagomError::Audio(String::from("sound has no samples")));
+        }
+        let buffer = self.context.create_buffer(1, samples.len() as u32, sample_rate as f32)
+            .map_err(|e| audio_error("couldn't create an audio buffer", e))?;
+        buffer.copy_to_channel(samples, 0).map_err(|e| audio_error("couldn't fill an audio buffer", e))?;
+
+        let mut sounds = self.sounds.borrow_mut();
+        sounds.push(SoundState::Ready(buffer));
+        Ok(sounds.len() - 1)
+    }
+
+    /// Generate a sound effect and load it, ready to play.
+    pub fn load_sfx(&mut self, params: &SfxParams) -> Result<SoundId, LagomError> {
+        self.load_samples(&params.generate(), SAMPLE_RATE)
+    }
+
+    pub fn is_loaded(&self, sound: SoundId) -> bool {
+        matches!(self.sounds.borrow().get(sound), Some(SoundState::Ready(_)))
+    }
+
+    /// The browser couldn't decode it.
+    pub fn has_failed(&self, sound: SoundId) -> bool {
+        matches!(self.sounds.borrow().get(sound), Some(SoundState::Failed))
+    }
+
+    /// Start playing a sound. `None` if it isn't loaded yet, or it's a one-shot and audio is still locked.
+    pub fn play(&self, sound: SoundId, params: &PlayParams) -> Option<VoiceId> {
+        let buffer = match self.sounds.borrow().get(sound) {
+            Some(SoundState::Ready(buffer)) => buffer.clone(),
+            _ => return None,
+        };
+        if !params.looping && !self.is_unlocked() {
+            return None;
+        }
+
+        match self.start_voice(&buffer, params) {
+            Ok(voice) => Some(voice),
+            Err(e) => {
+                console::error_1(&e.into());
+                None
+            }
+        }
+    }
+
+    fn start_voice(&self, buffer: &AudioBuffer, params: &PlayParams) -> Result<VoiceId, LagomError> {
+        let (volume, pitch, pan) = params.clamped();
+        let error = |e| audio_error("couldn't start sound", e);
+
+        let source = self.context.create_buffer_source().map_err(error)?;
+        source.set_buffer(Some(buffer));
+        source.set_loop(params.looping);
+        source.playback_rate().set_value(pitch);
+
+        let gain = self.context.create_gain().map_err(error)?;
+        gain.gain().set_value(volume);
+        let panner: StereoPannerNode = self.context.create_stereo_panner().map_err(error)?;
+        panner.pan().set_value(pan);
+
+        source.connect_with_audio_node(&gain).map_err(error)?;
+        gain.connect_with_audio_node(&panner).map_err(error)?;
+        panner.connect_with_audio_node(self.bus(params.bus)).map_err(error)?;
+
+        let id = self.next_voice.get();
+        self.next_voice.set(id.wrapping_add(1));
+
+        // Forget the voice once it finishes, or is stopped.
+        let source: AudioScheduledSourceNode = source.into();
+        let voices = self.voices.clone();
+        let on_ended = Closure::once_into_js(move || {
+            voices.borrow_mut().remove(&id);
+        });
+        source.set_onended(Some(on_ended.unchecked_ref()));
+        source.start().map_err(error)?;
+
+        self.voices.borrow_mut().insert(id, Voice { source, gain });
+        Ok(id)
+    }
+
+    /// Stop a playing sound. Does nothing if it already finished.
+    pub fn stop(&self, voice: VoiceId) {
+        if let Some(voice) = self.voices.borrow().get(&voice) {
+            let _ = voice.source.stop();
+        }
+    }
+
+    pub fn stop_all(&self) {
+        for voice in self.voices.borrow().values() {
+            let _ = voice.source.stop();
+        }
+    }
+
+    pub fn is_playing(&self, voice: VoiceId) -> bool {
+        self.voices.borrow().contains_key(&voice)
+    }
+
+    /// Change the volume of a sound that's playing.
+    pub fn set_volume(&self, voice: VoiceId, volume: f32) {
+        if let Some(voice) = self.voices.borrow().get(&voice) {
+            voice.gain.gain().set_value(volume.max(0.0));
+        }
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    /// 0 to 1.
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.mixer.master = volume.clamp(0.0, 1.0);
+        self.master.gain().set_value(self.mixer.master_gain());
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.mixer.muted = muted;
+        self.master.gain().set_value(self.mixer.master_gain());
+    }
+
+    /// 0 to 1.
+    pub fn set_bus_volume(&mut self, bus: Bus, volume: f32) {
+        self.mixer.set_bus_volume(bus, volume);
+        self.bus(bus).gain().set_value(self.mixer.bus_volume(bus));
+    }
+
+    fn bus(&self, bus: Bus) -> &GainNode {
+        match bus {
+            Bus::Music => &self.music,
+            Bus::Sfx => &self.sfx,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::audio::{Bus, Mixer, PlayParams};
+
+    #[test]
+    fn muting_keeps_the_volume() {
+        let mut mixer = Mixer { master: 0.5, muted: true, ..Mixer::default() };
+        assert_eq!(mixer.master_gain(), 0.0);
+        mixer.muted = false;
+        assert_eq!(mixer.master_gain(), 0.5);
+    }
+
+    #[test]
+    fn bus_volumes_are_clamped() {
+        let mut mixer = Mixer::default();
+        mixer.set_bus_volume(Bus::Music, 3.0);
+        mixer.set_bus_volume(Bus::Sfx, -1.0);
+        assert_eq!((mixer.bus_volume(Bus::Music), mixer.bus_volume(Bus::Sfx)), (1.0, 0.0));
+    }
+
+    #[test]
+    fn play_params_are_kept_in_range() {
+        let params = PlayParams { volume: -1.0, pitch: 0.0, pan: 4.0, ..PlayParams::music() };
+        assert_eq!(params.clamped(), (0.0, 1.0 / 16.0, 1.0));
+        assert!(params.looping);
+    }
+}
@@ -0,0 +1,416 @@
+use std::cmp::Ordering;
+
+use crate::material::{DEFAULT_MATERIAL, MaterialId};
+
+/// Floats per vertex: position (2), texcoord (2), colour (4).
+pub const VERTEX_SIZE: usize = 8;
+
+pub type LayerId = usize;
+
+/// The layer every sprite goes on unless told otherwise.
+pub const DEFAULT_LAYER: LayerId = 0;
+
+/// A named group of sprites. Layers with a lower `order` are drawn first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub order: i32,
+    /// Sort sprites in this layer by their y position instead of their depth, so things lower on screen are in front.
+    pub y_sort: bool,
+}
+
+/// How a single sprite should be placed on screen.
+///
+/// `x`/`y` is where the sprite's `origin` ends up. The origin is in texture pixels from the top left of the sprite
+/// and is also the point that rotation and scaling happen around.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawParams {
+    pub x: f32,
+    pub y: f32,
+    /// Clockwise rotation in radians.
+    pub rotation: f32,
+    pub origin: (f32, f32),
+    /// Region of the texture to draw as (x, y, width, height) in pixels. `None` draws the whole texture.
+    pub source: Option<(f32, f32, f32, f32)>,
+    pub scale: (f32, f32),
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// RGBA colour multiplied with the texture.
+    pub tint: [f32; 4],
+    pub alpha: f32,
+    pub layer: LayerId,
+    pub material: MaterialId,
+    /// Sort key within the layer, higher is drawn later. Sprites with equal keys keep the order they were drawn in.
+    pub depth: f32,
+}
+
+impl Default for DrawParams {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            rotation: 0.0,
+            origin: (0.0, 0.0),
+            source: None,
+            scale: (1.0, 1.0),
+            flip_x: false,
+            flip_y: false,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            alpha: 1.0,
+            layer: DEFAULT_LAYER,
+            material: DEFAULT_MATERIAL,
+            depth: 0.0,
+        }
+    }
+}
+
+impl DrawParams {
+    pub fn at(x: f32, y: f32) -> Self {
+        Self { x, y, ..Default::default() }
+    }
+}
+
+/// A run of vertices that can be drawn with a single draw call.
+#[derive(Debug, PartialEq)]
+pub struct Batch {
+    pub material: MaterialId,
+    pub texture: u32,
+    pub first: i32,
+    pub count: i32,
+}
+
+/// ((x, y), (u, v)) before the draw params are applied.
+type LocalVertex = ((f32, f32), (f32, f32));
+
+enum Geometry {
+    /// A textured rectangle.
+    Quad { width: f32, height: f32 },
+    /// Untextured triangles, three points each.
+    Triangles(Vec<(f32, f32)>),
+}
+
+struct Sprite {
+    texture: u32,
+    geometry: Geometry,
+    params: DrawParams,
+}
+
+/// Collects sprites, sorts them by layer and depth, then writes them into one interleaved vertex buffer, splitting
+/// it into batches whenever the material or texture changes.
+pub struct SpriteBatch {
+    pub vertices: Vec<f32>,
+    pub batches: Vec<Batch>,
+    layers: Vec<Layer>,
+    queue: Vec<Sprite>,
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        let default_layer = Layer { name: "default".to_string(), order: 0, y_sort: false };
+        Self { vertices: Vec::new(), batches: Vec::new(), layers: vec![default_layer], queue: Vec::new() }
+    }
+}
+
+impl SpriteBatch {
+    pub fn add_layer(&mut self, name: &str, order: i32, y_sort: bool) -> LayerId {
+        self.layers.push(Layer { name: name.to_string(), order, y_sort });
+        self.layers.len() - 1
+    }
+
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.layers.get_mut(id)
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.batches.clear();
+        self.queue.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn vertex_count(&self) -> i32 {
+        (self.vertices.len() / VERTEX_SIZE) as i32
+    }
+
+    pub fn push_sprite(&mut self, texture: u32, width: f32, height: f32, params: &DrawParams) {
+        self.queue.push(Sprite { texture, geometry: Geometry::Quad { width, height }, params: params.clone() });
+    }
+
+    /// Queue a list of triangles. Every vertex samples the middle of `texture`, which is expected to be plain white
+    /// so the tint comes through as is.
+    pub fn push_triangles(&mut self, texture: u32, points: Vec<(f32, f32)>, params: &DrawParams) {
+        self.queue.push(Sprite { texture, geometry: Geometry::Triangles(points), params: params.clone() });
+    }
+
+    /// Layers that were never added sort like the default layer.
+    fn sort_key(&self, params: &DrawParams) -> (i32, f32) {
+        let layer = self.layers.get(params.layer).unwrap_or(&self.layers[DEFAULT_LAYER]);
+        (layer.order, if layer.y_sort { params.y } else { params.depth })
+    }
+
+    /// Sort everything queued and write out the vertices and batches.
+    pub fn build(&mut self) {
+        let mut queue = std::mem::take(&mut self.queue);
+
+        // `sort_by` is stable, so equal keys stay in draw order.
+        queue.sort_by(|a, b| {
+            let (a_order, a_depth) = self.sort_key(&a.params);
+            let (b_order, b_depth) = self.sort_key(&b.params);
+            a_order.cmp(&b_order).then(a_depth.partial_cmp(&b_depth).unwrap_or(Ordering::Equal))
+        });
+
+        for sprite in queue.drain(..) {
+            match sprite.geometry {
+                Geometry::Quad { width, height } => self.write_quad(sprite.texture, width, height, &sprite.params),
+                Geometry::Triangles(points) => {
+                    let vertices: Vec<_> = points.into_iter().map(|point| (point, (0.5, 0.5))).collect();
+                    self.write_vertices(sprite.texture, &vertices, &sprite.params);
+                }
+            }
+        }
+
+        // Hand the allocation back for next frame.
+        self.queue = queue;
+    }
+
+    fn write_quad(&mut self, texture: u32, texture_width: f32, texture_height: f32, params: &DrawParams) {
+        let (sx, sy, width, height) = params.source.unwrap_or((0.0, 0.0, texture_width, texture_height));
+
+        let (left, right) = (sx / texture_width, (sx + width) / texture_width);
+        let (top, bottom) = (sy / texture_height, (sy + height) / texture_height);
+
+        let (u0, u1) = if params.flip_x { (right, left) } else { (left, right) };
+        let (v0, v1) = if params.flip_y { (bottom, top) } else { (top, bottom) };
+
+        let top_left = ((0.0, 0.0), (u0, v0));
+        let top_right = ((width, 0.0), (u1, v0));
+        let bottom_left = ((0.0, height), (u0, v1));
+        let bottom_right = ((width, height), (u1, v1));
+
+        self.write_vertices(texture, &[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right], params);
+    }
+
+    /// Transform local vertices by the draw params and append them.
+    fn write_vertices(&mut self, texture: u32, vertices: &[LocalVertex], params: &DrawParams) {
+        let (sin, cos) = params.rotation.sin_cos();
+        let (sx, sy) = params.scale;
+        let (ox, oy) = params.origin;
+
+        let [r, g, b, a] = params.tint;
+        let colour = [r, g, b, a * params.alpha];
+
+        let first = self.vertex_count();
+        for ((x, y), (u, v)) in vertices {
+            let x = (x - ox) * sx;
+            let y = (y - oy) * sy;
+            self.vertices.extend_from_slice(&[params.x + x * cos - y * sin, params.y + x * sin + y * cos, *u, *v]);
+            self.vertices.extend_from_slice(&colour);
+        }
+
+        let count = vertices.len() as i32;
+        match self.batches.last_mut() {
+            Some(batch) if batch.material == params.material && batch.texture == texture => batch.count += count,
+            _ => self.batches.push(Batch { material: params.material, texture, first, count }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::batch::{Batch, DrawParams, SpriteBatch, VERTEX_SIZE};
+
+    fn built(sprites: &[(u32, DrawParams)]) -> SpriteBatch {
+        let mut batch = SpriteBatch::default();
+        for (texture, params) in sprites {
+            batch.push_sprite(*texture, 1.0, 1.0, params);
+        }
+        batch.build();
+        batch
+    }
+
+    fn position(batch: &SpriteBatch, vertex: usize) -> (f32, f32) {
+        let i = vertex * VERTEX_SIZE;
+        (batch.vertices[i], batch.vertices[i + 1])
+    }
+
+    fn texcoord(batch: &SpriteBatch, vertex: usize) -> (f32, f32) {
+        let i = vertex * VERTEX_SIZE;
+        (batch.vertices[i + 2], batch.vertices[i + 3])
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!((actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+                "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn translate_and_scale() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams { scale: (2.0, 3.0), ..DrawParams::at(10.5, 20.0) };
+        batch.push_sprite(0, 16.0, 8.0, &params);
+        batch.build();
+
+        assert_eq!(batch.vertex_count(), 6);
+        assert_close(position(&batch, 0), (10.5, 20.0));
+        assert_close(position(&batch, 5), (42.5, 44.0));
+    }
+
+    #[test]
+    fn rotate_around_origin() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams {
+            rotation: std::f32::consts::FRAC_PI_2,
+            origin: (5.0, 5.0),
+            ..DrawParams::at(100.0, 100.0)
+        };
+        batch.push_sprite(0, 10.0, 10.0, &params);
+        batch.build();
+
+        // Top left corner swings round to the top right.
+        assert_close(position(&batch, 0), (105.0, 95.0));
+        // Bottom right corner swings round to the bottom left.
+        assert_close(position(&batch, 5), (95.0, 105.0));
+    }
+
+    #[test]
+    fn flip_swaps_texcoords() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams { flip_x: true, ..Default::default() };
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.build();
+
+        assert_close(texcoord(&batch, 0), (1.0, 0.0));
+        assert_close(texcoord(&batch, 5), (0.0, 1.0));
+    }
+
+    #[test]
+    fn tint_multiplies_alpha() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams { tint: [1.0, 0.5, 0.25, 0.5], alpha: 0.5, ..Default::default() };
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.build();
+
+        assert_eq!(&batch.vertices[4..8], &[1.0, 0.5, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn batches_split_on_texture_change() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams::default();
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.push_sprite(1, 1.0, 1.0, &params);
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.build();
+
+        assert_eq!(batch.batches, vec![
+            Batch { material: 0, texture: 0, first: 0, count: 12 },
+            Batch { material: 0, texture: 1, first: 12, count: 6 },
+            Batch { material: 0, texture: 0, first: 18, count: 6 },
+        ]);
+    }
+
+    #[test]
+    fn layers_draw_in_order() {
+        let mut batch = SpriteBatch::default();
+        let ui = batch.add_layer("ui", 10, false);
+        let background = batch.add_layer("background", -10, false);
+
+        batch.push_sprite(0, 1.0, 1.0, &DrawParams { layer: ui, ..Default::default() });
+        batch.push_sprite(1, 1.0, 1.0, &DrawParams::default());
+        batch.push_sprite(2, 1.0, 1.0, &DrawParams { layer: background, ..Default::default() });
+        batch.build();
+
+        let textures: Vec<u32> = batch.batches.iter().map(|b| b.texture).collect();
+        assert_eq!(textures, vec![2, 1, 0]);
+        assert_eq!(batch.layer_id("ui"), Some(ui));
+    }
+
+    #[test]
+    fn unknown_layers_draw_on_the_default_layer() {
+        let mut batch = SpriteBatch::default();
+        batch.add_layer("background", -10, false);
+
+        assert!(batch.layer_mut(7).is_none());
+
+        batch.push_sprite(0, 1.0, 1.0, &DrawParams { layer: 7, ..Default::default() });
+        batch.push_sprite(1, 1.0, 1.0, &DrawParams { layer: 1, ..Default::default() });
+        batch.build();
+
+        let textures: Vec<u32> = batch.batches.iter().map(|b| b.texture).collect();
+        assert_eq!(textures, vec![1, 0]);
+    }
+
+    #[test]
+    fn depth_sort_is_stable() {
+        let batch = built(&[
+            (0, DrawParams { depth: 1.0, ..Default::default() }),
+            (1, DrawParams { depth: 0.0, ..Default::default() }),
+            (2, DrawParams { depth: 1.0, ..Default::default() }),
+            (3, DrawParams { depth: -1.0, ..Default::default() }),
+        ]);
+
+        let textures: Vec<u32> = batch.batches.iter().map(|b| b.texture).collect();
+        assert_eq!(textures, vec![3, 1, 0, 2]);
+    }
+
+    #[test]
+    fn y_sort_layer_uses_position() {
+        let mut batch = SpriteBatch::default();
+        let entities = batch.add_layer("entities", 0, true);
+
+        batch.push_sprite(0, 1.0, 1.0, &DrawParams { layer: entities, ..DrawParams::at(0.0, 50.0) });
+        batch.push_sprite(1, 1.0, 1.0, &DrawParams { layer: entities, ..DrawParams::at(0.0, 10.0) });
+        batch.build();
+
+        let textures: Vec<u32> = batch.batches.iter().map(|b| b.texture).collect();
+        assert_eq!(textures, vec![1, 0]);
+    }
+
+    #[test]
+    fn batches_split_on_material_change() {
+        let batch = built(&[
+            (0, DrawParams::default()),
+            (0, DrawParams { material: 1, ..Default::default() }),
+            (0, DrawParams { material: 1, ..Default::default() }),
+        ]);
+
+        assert_eq!(batch.batches, vec![
+            Batch { material: 0, texture: 0, first: 0, count: 6 },
+            Batch { material: 1, texture: 0, first: 6, count: 12 },
+        ]);
+    }
+
+    #[test]
+    fn triangles_batch_with_sprites() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams { tint: [1.0, 0.0, 0.0, 1.0], ..DrawParams::at(10.0, 10.0) };
+        batch.push_sprite(0, 1.0, 1.0, &params);
+        batch.push_triangles(0, vec![(0.0, 0.0), (5.0, 0.0), (0.0, 5.0)], &params);
+        batch.build();
+
+        assert_eq!(batch.batches, vec![Batch { material: 0, texture: 0, first: 0, count: 9 }]);
+        assert_close(position(&batch, 7), (15.0, 10.0));
+        assert_close(texcoord(&batch, 7), (0.5, 0.5));
+        assert_eq!(&batch.vertices[7 * VERTEX_SIZE + 4..8 * VERTEX_SIZE], &[1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn source_rect_sets_size_and_texcoords() {
+        let mut batch = SpriteBatch::default();
+        let params = DrawParams { source: Some((16.0, 8.0, 8.0, 8.0)), ..DrawParams::at(100.0, 100.0) };
+        batch.push_sprite(0, 32.0, 16.0, &params);
+        batch.build();
+
+        assert_close(position(&batch, 5), (108.0, 108.0));
+        assert_close(texcoord(&batch, 0), (0.5, 0.5));
+        assert_close(texcoord(&batch, 5), (0.75, 1.0));
+    }
+}
@@ -0,0 +1,222 @@
+use cgmath::{Matrix4, Rad, SquareMatrix, Vector2, vec2, vec3, vec4};
+
+/// A 2D camera. `position` is the world point that sits in the middle of the viewport.
+pub struct Camera2D {
+    pub position: Vector2<f32>,
+    pub zoom: f32,
+    /// Clockwise rotation in radians.
+    pub rotation: f32,
+    /// Size of the area the camera renders to, in screen pixels.
+    pub viewport: Vector2<f32>,
+    /// World rectangle (min, max) the view is kept inside of.
+    pub bounds: Option<(Vector2<f32>, Vector2<f32>)>,
+
+    follow_target: Option<Vector2<f32>>,
+    /// How quickly the camera catches up to the follow target. Higher is snappier, 0 snaps instantly.
+    pub follow_smoothing: f32,
+
+    shake_intensity: f32,
+    shake_duration: f32,
+    shake_remaining: f32,
+    shake_offset: Vector2<f32>,
+    rng: u32,
+}
+
+impl Camera2D {
+    pub fn new(viewport_width: f32, viewport_height: f32) -> Self {
+        Self {
+            position: vec2(viewport_width / 2.0, viewport_height / 2.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport: vec2(viewport_width, viewport_height),
+            bounds: None,
+            follow_target: None,
+            follow_smoothing: 0.0,
+            shake_intensity: 0.0,
+            shake_duration: 0.0,
+            shake_remaining: 0.0,
+            shake_offset: vec2(0.0, 0.0),
+            rng: 0x9E37_79B9,
+        }
+    }
+
+    /// Change the viewport size, moving `position` so the world point at the top-left corner stays put.
+    pub fn resize(&mut self, viewport_width: f32, viewport_height: f32) {
+        let viewport = vec2(viewport_width, viewport_height);
+        self.position += (viewport - self.viewport) / (2.0 * self.zoom);
+        self.viewport = viewport;
+    }
+
+    /// Track a world position. Call this every frame with the latest position of whatever is being followed.
+    pub fn follow(&mut self, target: Vector2<f32>) {
+        self.follow_target = Some(target);
+    }
+
+    pub fn stop_following(&mut self) {
+        self.follow_target = None;
+    }
+
+    /// Shake the camera by up to `intensity` world units, fading out over `duration` seconds.
+    pub fn shake(&mut self, intensity: f32, duration: f32) {
+        self.shake_intensity = intensity;
+        self.shake_duration = duration;
+        self.shake_remaining = duration;
+    }
+
+    /// Advance following and shaking. `delta` is in seconds.
+    pub fn update(&mut self, delta: f32) {
+        if let Some(target) = self.follow_target {
+            if self.follow_smoothing <= 0.0 {
+                self.position = target;
+            } else {
+                // Framerate independent exponential smoothing.
+                let t = 1.0 - (-self.follow_smoothing * delta).exp();
+                self.position += (target - self.position) * t;
+            }
+        }
+
+        self.clamp_to_bounds();
+
+        if self.shake_remaining > 0.0 {
+            self.shake_remaining = (self.shake_remaining - delta).max(0.0);
+            let strength = self.shake_intensity * self.shake_remaining / self.shake_duration;
+            self.shake_offset = vec2(self.next_random() * strength, self.next_random() * strength);
+        } else {
+            self.shake_offset = vec2(0.0, 0.0);
+        }
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        if let Some((min, max)) = self.bounds {
+            let half_view = self.viewport / (2.0 * self.zoom);
+
+            let clamp_axis = |pos: f32, min: f32, max: f32, half: f32| {
+                if max - min < half * 2.0 {
+                    // The view is bigger than the bounds, centre it instead.
+                    (min + max) / 2.0
+                } else {
+                    pos.max(min + half).min(max - half)
+                }
+            };
+
+            self.position.x = clamp_axis(self.position.x, min.x, max.x, half_view.x);
+            self.position.y = clamp_axis(self.position.y, min.y, max.y, half_view.y);
+        }
+    }
+
+    /// xorshift, returning a value in [-1, 1].
+    fn next_random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+
+    /// World to screen transform.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let centre = self.position + self.shake_offset;
+        Matrix4::from_translation(vec3(self.viewport.x / 2.0, self.viewport.y / 2.0, 0.0))
+            * Matrix4::from_angle_z(Rad(-self.rotation))
+            * Matrix4::from_scale(self.zoom)
+            * Matrix4::from_translation(vec3(-centre.x, -centre.y, 0.0))
+    }
+
+    pub fn world_to_screen(&self, point: Vector2<f32>) -> Vector2<f32> {
+        let p = self.view_matrix() * vec4(point.x, point.y, 0.0, 1.0);
+        vec2(p.x, p.y)
+    }
+
+    pub fn screen_to_world(&self, point: Vector2<f32>) -> Vector2<f32> {
+        let inverse = self.view_matrix().invert().unwrap_or_else(Matrix4::identity);
+        let p = inverse * vec4(point.x, point.y, 0.0, 1.0);
+        vec2(p.x, p.y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Vector2, vec2};
+
+    use crate::camera::Camera2D;
+
+    fn assert_close(actual: Vector2<f32>, expected: Vector2<f32>) {
+        assert!((actual.x - expected.x).abs() < 1e-3 && (actual.y - expected.y).abs() < 1e-3,
+                "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn default_camera_is_identity() {
+        let camera = Camera2D::new(320.0, 240.0);
+        assert_close(camera.world_to_screen(vec2(10.0, 20.0)), vec2(10.0, 20.0));
+    }
+
+    #[test]
+    fn position_is_centre_of_screen() {
+        let mut camera = Camera2D::new(320.0, 240.0);
+        camera.position = vec2(1000.0, 1000.0);
+        camera.zoom = 2.0;
+
+        assert_close(camera.world_to_screen(vec2(1000.0, 1000.0)), vec2(160.0, 120.0));
+        assert_close(camera.world_to_screen(vec2(1010.0, 1000.0)), vec2(180.0, 120.0));
+    }
+
+    #[test]
+    fn screen_to_world_round_trip() {
+        let mut camera = Camera2D::new(320.0, 240.0);
+        camera.position = vec2(-50.0, 75.0);
+        camera.zoom = 3.0;
+        camera.rotation = 0.7;
+
+        let world = vec2(12.0, -34.0);
+        assert_close(camera.screen_to_world(camera.world_to_screen(world)), world);
+    }
+
+    #[test]
+    fn resizing_keeps_the_top_left_corner() {
+        let mut camera = Camera2D::new(320.0, 240.0);
+        camera.resize(640.0, 360.0);
+        assert_close(camera.world_to_screen(vec2(0.0, 0.0)), vec2(0.0, 0.0));
+        assert_close(camera.world_to_screen(vec2(10.0, 20.0)), vec2(10.0, 20.0));
+
+        camera.zoom = 2.0;
+        camera.resize(320.0, 240.0);
+        assert_close(camera.world_to_screen(vec2(0.0, 0.0)), vec2(-320.0, -180.0));
+    }
+
+    #[test]
+    fn follow_with_smoothing_converges() {
+        let mut camera = Camera2D::new(320.0, 240.0);
+        camera.follow_smoothing = 5.0;
+        camera.follow(vec2(500.0, 500.0));
+
+        camera.update(0.1);
+        assert!(camera.position.x > 160.0 && camera.position.x < 500.0);
+
+        for _ in 0..100 {
+            camera.update(0.1);
+        }
+        assert_close(camera.position, vec2(500.0, 500.0));
+    }
+
+    #[test]
+    fn clamps_to_bounds() {
+        let mut camera = Camera2D::new(100.0, 100.0);
+        camera.bounds = Some((vec2(0.0, 0.0), vec2(1000.0, 80.0)));
+        camera.follow(vec2(-200.0, 0.0));
+        camera.update(0.016);
+
+        // Left edge of the view sits on the bounds, the bounds are shorter than the view so y is centred.
+        assert_close(camera.position, vec2(50.0, 40.0));
+    }
+
+    #[test]
+    fn shake_wears_off() {
+        let mut camera = Camera2D::new(100.0, 100.0);
+        camera.shake(10.0, 0.5);
+        camera.update(0.1);
+        assert_ne!(camera.world_to_screen(vec2(50.0, 50.0)), vec2(50.0, 50.0));
+
+        camera.update(1.0);
+        assert_close(camera.world_to_screen(vec2(50.0, 50.0)), vec2(50.0, 50.0));
+    }
+}
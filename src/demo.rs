@@ -0,0 +1,37 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{Game, LagomGame, run};
+use crate::assets::Handle;
+use crate::batch::DrawParams;
+
+const BACKGROUND: &str = "assets/bg_tileable.png";
+
+/// Scrolls the background across the screen.
+#[derive(Default)]
+struct Demo {
+    background: Option<Handle>,
+    scroll: f64,
+}
+
+impl Game for Demo {
+    fn init(&mut self, lagom: &mut LagomGame) {
+        let background = lagom.assets_mut().load_image(BACKGROUND);
+        lagom.wait_for(background);
+        self.background = Some(background);
+    }
+
+    fn update(&mut self, _lagom: &mut LagomGame, delta: f64) {
+        self.scroll = (self.scroll + delta * 20.0) % 100.0;
+    }
+
+    fn render(&mut self, lagom: &mut LagomGame) {
+        if let Some(background) = self.background.and_then(|handle| lagom.assets().texture(handle)) {
+            lagom.draw(background, DrawParams::at(4.0 + self.scroll as f32, 10.0));
+        }
+    }
+}
+
+#[wasm_bindgen(start)]
+pub fn start() -> Result<(), JsValue> {
+    run(Demo::default())
+}
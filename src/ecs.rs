@@ -4,9 +4,14 @@ use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
 use std::ops::DerefMut;
 
+use crate::batch::DrawParams;
+use crate::LagomGame;
+use crate::text::{FontId, TextStyle};
+
 // Entity ID, Component Type, Component
 // struct ComponentSlice(usize, usize, Box<dyn std::any::Any>);
-struct ComponentSlice(usize, TypeId, Box<dyn std::any::Any>);
+/// One component of one entity, as seen by a `Filter`.
+pub struct ComponentSlice(usize, TypeId, Box<dyn std::any::Any>);
 
 
 trait ComponentType {
@@ -26,18 +31,21 @@ impl<T: 'static> ComponentType for RefCell<T> {
     }
 }
 
-trait Filter
+/// Picks entities in `World::get_entities_with_filter`. An entity matches when each filter matches one of its
+/// components.
+pub trait Filter
 {
     fn matches(&self, slice: &ComponentSlice) -> bool;
 }
 
-struct HasComp {
+/// Matches entities that have a component of type `T`, see `HasComp::new`.
+pub struct HasComp {
     type_id: TypeId,
 }
 
 // TODO we can do the downcast thing instead of TypeId?
 impl HasComp {
-    fn new<T: 'static>() -> Self {
+    pub fn new<T: 'static>() -> Self {
         return HasComp { type_id: TypeId::of::<T>() };
     }
 }
@@ -49,15 +57,15 @@ impl Filter for HasComp {
 }
 
 #[derive(Default)]
-struct Game {
+pub struct World {
     components: Vec<ComponentSlice>,
     entity_count: usize,
     // unique_component_count: usize,
     // component_types: Vec<Box<dyn ComponentType>>,
 }
 
-impl Game {
-    fn create_entity(&mut self) -> usize {
+impl World {
+    pub fn create_entity(&mut self) -> usize {
         let entity_id = self.entity_count;
         self.entity_count += 1;
         entity_id
@@ -67,11 +75,11 @@ impl Game {
         creator.create_entity(self);
     }
 
-    fn add_component<T: 'static>(&mut self, entity: usize, component: T) {
+    pub fn add_component<T: 'static>(&mut self, entity: usize, component: T) {
         self.components.push(ComponentSlice(entity, TypeId::of::<T>(), Box::new(RefCell::new(component))));
     }
 
-    fn get_component<T: 'static>(&mut self, entity: usize) -> Option<RefMut<T>> {
+    pub fn get_component<T: 'static>(&mut self, entity: usize) -> Option<RefMut<T>> {
         // TODO we can do the downcast thing here instead of TypeId?
         match self.components.iter_mut().find(|x| x.0 == entity && x.1 == TypeId::of::<T>()) {
             None => { None }
@@ -86,7 +94,7 @@ impl Game {
         }
     }
 
-    fn get_entities_with_filter(&mut self, filters: &[&dyn Filter]) -> Vec<usize> {
+    pub fn get_entities_with_filter(&mut self, filters: &[&dyn Filter]) -> Vec<usize> {
         let mut matches = Vec::new();
 
         for entity_id in 0..self.entity_count {
@@ -124,21 +132,47 @@ trait Updatable {
 }
 
 trait EntityCreator {
-    fn create_entity(&self, game: &mut Game) -> usize;
+    fn create_entity(&self, game: &mut World) -> usize;
+}
+
+/// How to draw an entity's `TextValue`.
+#[derive(Clone)]
+pub struct TextRenderer {
+    pub font: FontId,
+    pub style: TextStyle,
+    pub colour: [f32; 4],
+}
+
+impl TextRenderer {
+    /// White text in the default style.
+    pub fn new(font: FontId) -> Self {
+        Self { font, style: TextStyle::default(), colour: [1.0, 1.0, 1.0, 1.0] }
+    }
 }
 
-struct TextRenderer {}
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
 
 struct TextBox {}
 
-struct TextValue(String);
+/// The text an entity with a `TextRenderer` draws.
+pub struct TextValue(pub String);
+
+impl TextValue {
+    pub fn new(text: &str) -> Self {
+        Self(text.to_string())
+    }
+}
 
 impl EntityCreator for TextBox {
-    fn create_entity(&self, game: &mut Game) -> usize {
+    fn create_entity(&self, game: &mut World) -> usize {
         let entity_id = game.create_entity();
 
-        game.add_component(entity_id, TextRenderer {});
-        game.add_component(entity_id, TextValue("HAHAHA".to_string()));
+        game.add_component(entity_id, TextRenderer::default());
+        game.add_component(entity_id, TextValue::new("HAHAHA"));
 
         entity_id
     }
@@ -146,14 +180,33 @@ impl EntityCreator for TextBox {
 
 struct Parent(usize);
 
-struct Position(f64, f64);
+/// Where an entity is in the world.
+pub struct Position(pub f64, pub f64);
+
+impl World {
+    /// Draw every entity with a `TextRenderer` and `TextValue`, at its `Position` if it has one. Entities whose font
+    /// isn't loaded are skipped.
+    pub fn render_text(&mut self, lagom: &mut LagomGame) {
+        let renderer_filter = HasComp::new::<TextRenderer>();
+        let value_filter = HasComp::new::<TextValue>();
+
+        for entity in self.get_entities_with_filter(&[&renderer_filter, &value_filter]) {
+            let renderer = self.get_component::<TextRenderer>(entity).map(|r| (*r).clone()).unwrap();
+            let text = self.get_component::<TextValue>(entity).map(|value| value.0.clone()).unwrap();
+            let (x, y) = self.get_component::<Position>(entity).map(|pos| (pos.0, pos.1)).unwrap_or((0.0, 0.0));
+
+            let params = DrawParams { tint: renderer.colour, ..DrawParams::at(x as f32, y as f32) };
+            lagom.draw_text(renderer.font, &text, &renderer.style, params);
+        }
+    }
+}
 
 #[cfg(test)]
 mod test {
     use std::any::TypeId;
     use std::cell::{RefCell, RefMut};
 
-    use crate::ecs::{ComponentSlice, Game, HasComp, TextBox};
+    use crate::ecs::{ComponentSlice, HasComp, TextBox, TextRenderer, TextValue, World};
 
     struct TestComp(u32);
 
@@ -161,7 +214,7 @@ mod test {
 
     #[test]
     fn test_hello() {
-        let mut game = Game::default();
+        let mut game = World::default();
         let e1 = game.create_entity();
         let e2 = game.create_entity();
 
@@ -204,6 +257,18 @@ mod test {
         println!("{}", a.0);
     }
 
+    #[test]
+    fn text_entities_are_built_from_public_components() {
+        let mut world = World::default();
+        let entity = world.create_entity();
+        world.add_component(entity, TextRenderer::new(2));
+        world.add_component(entity, TextValue::new("Score: 10"));
+
+        let filters = [HasComp::new::<TextRenderer>(), HasComp::new::<TextValue>()];
+        assert_eq!(world.get_entities_with_filter(&[&filters[0], &filters[1]]), vec![entity]);
+        assert_eq!(world.get_component::<TextRenderer>(entity).map(|r| r.font), Some(2));
+    }
+
 // use crate::ecs::{Component, ComponentId, Game};
 //
 // struct TestComp;
@@ -215,7 +280,7 @@ mod test {
 //     let comp1 = TestComp;
 //     let comp2 = TestComp;
 //
-//     let mut game = Game::default();
+//     let mut game = World::default();
 //     let e = game.create_entity();
 //     e.with(comp1);
 // }
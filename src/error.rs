@@ -0,0 +1,157 @@
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use crate::renderer::TextureError;
+
+/// Everything that can go wrong inside the engine. Converts to a `JsValue` so it can be returned from wasm entry
+/// points and shows up readably in the browser console.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LagomError {
+    /// No element with this ID, or it isn't a `<canvas>`.
+    CanvasMissing(String),
+    /// The browser wouldn't give us this context, e.g. "webgl2".
+    ContextUnavailable(String),
+    /// A GL object couldn't be created, usually because the context was lost.
+    GlResource(&'static str),
+    ShaderCompile { stage: ShaderStage, errors: Vec<ShaderLogLine>, log: String },
+    ShaderLink(String),
+    /// A uniform that doesn't exist or can't hold the value given.
+    Uniform(String),
+    TextureLoad(TextureError),
+    /// An image, font or other asset file couldn't be read.
+    AssetDecode(String),
+    /// An asset couldn't be downloaded.
+    AssetFetch(String),
+    /// Web Audio isn't available, or refused to do something.
+    Audio(String),
+    /// Saved input bindings that couldn't be read back.
+    InvalidBindings(String),
+    /// An action or axis name that's empty or has whitespace in it, so it couldn't be saved.
+    InvalidActionName(String),
+    /// No material was created with this ID.
+    UnknownMaterial(usize),
+    /// No font was loaded with this ID.
+    UnknownFont(usize),
+    /// The drawn frame couldn't be read back.
+    ReadPixels(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+/// One error from a shader info log, with the source line it points at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderLogLine {
+    pub line: usize,
+    pub message: String,
+    pub source: String,
+}
+
+/// Pick the `ERROR: 0:12: message` lines out of a GLSL info log and look up the source line each refers to.
+pub fn parse_shader_log(log: &str, source: &str) -> Vec<ShaderLogLine> {
+    let source_lines: Vec<&str> = source.lines().collect();
+
+    log.lines()
+        .filter_map(|entry| {
+            let rest = entry.trim().strip_prefix("ERROR:")?;
+            let mut parts = rest.splitn(3, ':');
+            let _file = parts.next()?;
+            let line = parts.next()?.trim().parse::<usize>().ok()?;
+            let message = parts.next()?.trim().to_string();
+            let source = source_lines.get(line.wrapping_sub(1)).map_or("", |s| s.trim()).to_string();
+            Some(ShaderLogLine { line, message, source })
+        })
+        .collect()
+}
+
+impl fmt::Display for LagomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LagomError::CanvasMissing(id) => write!(f, "No <canvas> element with id \"{}\" in the page", id),
+            LagomError::ContextUnavailable(kind) => write!(f, "Couldn't get a {} context from the canvas", kind),
+            LagomError::GlResource(what) => write!(f, "Couldn't create a {}, the WebGL context may have been lost", what),
+            LagomError::ShaderCompile { stage, errors, log } => {
+                write!(f, "{:?} shader failed to compile", stage)?;
+                if errors.is_empty() {
+                    return write!(f, ": {}", log.trim());
+                }
+                for error in errors {
+                    write!(f, "\n  line {}: {}\n    {}", error.line, error.message, error.source)?;
+                }
+                Ok(())
+            }
+            LagomError::ShaderLink(log) => write!(f, "Shader program failed to link: {}", log.trim()),
+            LagomError::Uniform(message) => write!(f, "{}", message),
+            LagomError::TextureLoad(e) => write!(f, "{}", e),
+            LagomError::AssetDecode(message) => write!(f, "Couldn't decode asset: {}", message),
+            LagomError::AssetFetch(message) => write!(f, "Couldn't download asset: {}", message),
+            LagomError::Audio(message) => write!(f, "Audio error: {}", message),
+            LagomError::InvalidBindings(message) => write!(f, "Couldn't read input bindings: {}", message),
+            LagomError::InvalidActionName(name) => write!(f, "Invalid action or axis name \"{}\"", name),
+            LagomError::UnknownMaterial(id) => write!(f, "No material with ID {}", id),
+            LagomError::UnknownFont(id) => write!(f, "No font with ID {}", id),
+            LagomError::ReadPixels(message) => write!(f, "Couldn't read pixels: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LagomError {}
+
+impl From<TextureError> for LagomError {
+    fn from(e: TextureError) -> Self {
+        LagomError::TextureLoad(e)
+    }
+}
+
+impl From<image::ImageError> for LagomError {
+    fn from(e: image::ImageError) -> Self {
+        LagomError::AssetDecode(e.to_string())
+    }
+}
+
+impl From<LagomError> for JsValue {
+    fn from(e: LagomError) -> Self {
+        js_sys::Error::new(&e.to_string()).into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{LagomError, parse_shader_log, ShaderLogLine, ShaderStage};
+
+    const SOURCE: &str = "#version 300 es\nprecision highp float;\nout vec4 outColor;\nvoid main() {\n   outColor = colour;\n}";
+
+    #[test]
+    fn shader_log_points_at_source_lines() {
+        let log = "ERROR: 0:5: 'colour' : undeclared identifier\nERROR: 1 compilation errors.  No code generated.\n";
+        assert_eq!(parse_shader_log(log, SOURCE), vec![ShaderLogLine {
+            line: 5,
+            message: String::from("'colour' : undeclared identifier"),
+            source: String::from("outColor = colour;"),
+        }]);
+    }
+
+    #[test]
+    fn shader_error_message_has_line_info() {
+        let log = "ERROR: 0:5: 'colour' : undeclared identifier\n";
+        let error = LagomError::ShaderCompile {
+            stage: ShaderStage::Fragment,
+            errors: parse_shader_log(log, SOURCE),
+            log: log.to_string(),
+        };
+        let message = error.to_string();
+        assert!(message.contains("Fragment shader"));
+        assert!(message.contains("line 5"));
+        assert!(message.contains("outColor = colour;"));
+    }
+
+    #[test]
+    fn unparsed_logs_are_kept() {
+        let error = LagomError::ShaderCompile { stage: ShaderStage::Vertex, errors: Vec::new(), log: String::from("oops") };
+        assert_eq!(error.to_string(), "Vertex shader failed to compile: oops");
+    }
+}
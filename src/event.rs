@@ -0,0 +1,13 @@
+use crate::assets::Handle;
+
+/// Something that happened outside the game since the last update, from `LagomGame::events`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The screen changed size. `width` and `height` are in game pixels, the size the camera now covers.
+    Resized { width: i32, height: i32, pixel_ratio: f64 },
+    /// A gamepad showed up. Browsers only report one after a button on it is pressed.
+    GamepadConnected { index: u32, name: String },
+    GamepadDisconnected { index: u32 },
+    /// A new version of an asset was loaded in place by hot reloading.
+    AssetReloaded { handle: Handle },
+}
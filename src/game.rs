@@ -0,0 +1,22 @@
+use crate::event::Event;
+use crate::LagomGame;
+
+/// A game for the engine to run, see `run`. `self` is the game's own state, kept between frames. Every method has
+/// an empty default so games only write the ones they need. All times are in seconds.
+pub trait Game {
+    /// Called once before the first frame, e.g. to load assets and push the first scene.
+    fn init(&mut self, _lagom: &mut LagomGame) {}
+
+    /// Called once a frame with the real time since the last frame.
+    fn update(&mut self, _lagom: &mut LagomGame, _delta: f64) {}
+
+    /// Called at a steady rate, `LagomGame::set_update_rate` times a second, no matter the frame rate. Put physics
+    /// and game logic here.
+    fn fixed_update(&mut self, _lagom: &mut LagomGame, _step: f64) {}
+
+    /// Draw the frame, after `update` and the scenes. Use `LagomGame::alpha` to blend between fixed updates.
+    fn render(&mut self, _lagom: &mut LagomGame) {}
+
+    /// Called for each event before the frame's updates. They're also in `LagomGame::events` until the frame ends.
+    fn on_event(&mut self, _lagom: &mut LagomGame, _event: &Event) {}
+}
@@ -0,0 +1,163 @@
+/// What to run this frame, from `GameLoop::advance`. All times are in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    /// How many fixed updates to run, each `step` long.
+    pub steps: u32,
+    pub step: f64,
+    /// Real time since the last frame, for the variable rate update.
+    pub delta: f64,
+    /// How far between the last fixed update and the next one this frame is, 0 to 1. Use it to blend positions when
+    /// drawing so movement stays smooth when the frame rate and update rate differ.
+    pub alpha: f64,
+}
+
+/// Turns real frame times into a steady number of fixed updates. Doesn't touch the browser, feed it timestamps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameLoop {
+    step: f64,
+    max_steps: u32,
+    accumulator: f64,
+    last: Option<f64>,
+}
+
+impl GameLoop {
+    /// Falls back to 60 updates a second if `update_rate` isn't a positive number.
+    pub fn new(update_rate: f64) -> Self {
+        let step = step_for(update_rate).unwrap_or(1.0 / 60.0);
+        Self { step, max_steps: 5, accumulator: 0.0, last: None }
+    }
+
+    /// Fixed updates per second. Rates that aren't positive numbers are ignored.
+    pub fn set_update_rate(&mut self, update_rate: f64) {
+        if let Some(step) = step_for(update_rate) {
+            self.step = step;
+        }
+    }
+
+    /// The most fixed updates run in one frame. After a long pause, e.g. a tab switch, the rest of the time is dropped
+    /// rather than trying to catch up.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1);
+    }
+
+    /// Start a frame at `now` seconds.
+    pub fn advance(&mut self, now: f64) -> Frame {
+        let delta = self.last.map_or(0.0, |last| (now - last).max(0.0));
+        self.last = Some(now);
+        // Anything past one step more than the limit would be dropped below anyway.
+        self.accumulator = (self.accumulator + delta).min(self.step * (self.max_steps + 1) as f64);
+        // The variable rate update gets no more time than the fixed steps did, so a tab switch doesn't make things
+        // jump either.
+        let delta = delta.min(self.step * self.max_steps as f64);
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps > self.max_steps {
+            steps = self.max_steps;
+            self.accumulator %= self.step;
+        }
+
+        Frame { steps, step: self.step, delta, alpha: self.accumulator / self.step }
+    }
+
+    /// Forget the time passing up to `now`, e.g. while paused, so it doesn't all arrive at once on resuming.
+    pub fn skip(&mut self, now: f64) {
+        self.last = Some(now);
+    }
+}
+
+/// Seconds per update for `update_rate` updates a second, if that's a usable rate.
+fn step_for(update_rate: f64) -> Option<f64> {
+    let step = 1.0 / update_rate;
+    if update_rate > 0.0 && step.is_finite() && step > 0.0 {
+        Some(step)
+    } else {
+        None
+    }
+}
+
+impl Default for GameLoop {
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game_loop::GameLoop;
+
+    #[test]
+    fn first_frame_has_no_time() {
+        let mut game_loop = GameLoop::new(60.0);
+        let frame = game_loop.advance(12.0);
+        assert_eq!((frame.steps, frame.delta, frame.alpha), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn steps_are_independent_of_frame_rate() {
+        // One second at 144Hz and at 30Hz both give 60 updates.
+        for frame_rate in [144.0, 30.0].iter() {
+            let mut game_loop = GameLoop::new(60.0);
+            let mut steps = 0;
+            game_loop.advance(0.0);
+            for frame in 1..=*frame_rate as u32 {
+                steps += game_loop.advance(frame as f64 / frame_rate).steps;
+            }
+            assert!((59..=60).contains(&steps), "{} steps at {}Hz", steps, frame_rate);
+        }
+    }
+
+    #[test]
+    fn leftover_time_becomes_alpha() {
+        let mut game_loop = GameLoop::new(10.0);
+        game_loop.advance(0.0);
+        let frame = game_loop.advance(0.25);
+        assert_eq!(frame.steps, 2);
+        assert!((frame.alpha - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut game_loop = GameLoop::new(60.0);
+        game_loop.set_max_steps(4);
+        game_loop.advance(0.0);
+        let frame = game_loop.advance(10.0);
+        assert_eq!(frame.steps, 4);
+        assert!(frame.alpha < 1.0);
+        assert_eq!(game_loop.advance(10.0).steps, 0);
+    }
+
+    #[test]
+    fn long_frames_clamp_delta_too() {
+        let mut game_loop = GameLoop::new(60.0);
+        game_loop.set_max_steps(4);
+        game_loop.advance(0.0);
+        assert!((game_loop.advance(3600.0).delta - 4.0 / 60.0).abs() < 1e-9);
+        assert!((game_loop.advance(3600.01).delta - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bad_update_rates_are_ignored() {
+        let mut game_loop = GameLoop::new(f64::NAN);
+        game_loop.advance(0.0);
+        assert_eq!(game_loop.advance(1.0).steps, 5);
+
+        for rate in [-60.0, 0.0, f64::NAN, f64::INFINITY].iter() {
+            game_loop.set_update_rate(*rate);
+            assert!((game_loop.advance(2.0).step - 1.0 / 60.0).abs() < 1e-12, "{} changed the step", rate);
+        }
+    }
+
+    #[test]
+    fn skipped_time_is_dropped() {
+        let mut game_loop = GameLoop::new(60.0);
+        game_loop.advance(0.0);
+        game_loop.skip(5.0);
+        let frame = game_loop.advance(5.0);
+        assert_eq!((frame.steps, frame.delta), (0, 0.0));
+    }
+}
@@ -0,0 +1,269 @@
+use cgmath::{InnerSpace, Vector2, vec2};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::GamepadMappingType;
+
+/// Buttons by their place on the controller in the browser's standard mapping, so `South` is A on an Xbox pad and
+/// Cross on a PlayStation one. Pads without the standard mapping only have `Other` buttons, by index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Home,
+    Other(u32),
+}
+
+const STANDARD_BUTTONS: [GamepadButton; 17] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::West,
+    GamepadButton::North,
+    GamepadButton::LeftBumper,
+    GamepadButton::RightBumper,
+    GamepadButton::LeftTrigger,
+    GamepadButton::RightTrigger,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+    GamepadButton::Home,
+];
+
+impl GamepadButton {
+    pub fn from_index(index: u32, standard: bool) -> Self {
+        match STANDARD_BUTTONS.get(index as usize) {
+            Some(button) if standard => *button,
+            _ => GamepadButton::Other(index),
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        match self {
+            GamepadButton::Other(index) => index,
+            button => STANDARD_BUTTONS.iter().position(|b| *b == button).unwrap_or_default() as u32,
+        }
+    }
+}
+
+/// Sticks in the standard mapping. Y is positive down, like the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    Other(u32),
+}
+
+impl GamepadAxis {
+    pub fn from_index(index: u32, standard: bool) -> Self {
+        match index {
+            0 if standard => GamepadAxis::LeftX,
+            1 if standard => GamepadAxis::LeftY,
+            2 if standard => GamepadAxis::RightX,
+            3 if standard => GamepadAxis::RightY,
+            _ => GamepadAxis::Other(index),
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        match self {
+            GamepadAxis::LeftX => 0,
+            GamepadAxis::LeftY => 1,
+            GamepadAxis::RightX => 2,
+            GamepadAxis::RightY => 3,
+            GamepadAxis::Other(index) => index,
+        }
+    }
+
+    /// The other half of the stick this axis belongs to, so both can share a round deadzone.
+    fn partner(self) -> Option<GamepadAxis> {
+        match self {
+            GamepadAxis::LeftX => Some(GamepadAxis::LeftY),
+            GamepadAxis::LeftY => Some(GamepadAxis::LeftX),
+            GamepadAxis::RightX => Some(GamepadAxis::RightY),
+            GamepadAxis::RightY => Some(GamepadAxis::RightX),
+            GamepadAxis::Other(_) => None,
+        }
+    }
+}
+
+/// One pad as read from `navigator.getGamepads()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadSnapshot {
+    pub index: u32,
+    pub name: String,
+    /// Uses the standard mapping, so buttons and axes have names.
+    pub standard: bool,
+    /// Pressed and how far, 0 to 1. Only triggers go in between.
+    pub buttons: Vec<(bool, f32)>,
+    pub axes: Vec<f32>,
+}
+
+/// A connected pad's state for the current frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gamepad {
+    pub name: String,
+    pub standard: bool,
+    buttons: Vec<f32>,
+    axes: Vec<f32>,
+}
+
+impl Gamepad {
+    pub fn new(snapshot: &GamepadSnapshot) -> Self {
+        Self {
+            name: snapshot.name.clone(),
+            standard: snapshot.standard,
+            buttons: snapshot.buttons.iter().map(|(_, value)| *value).collect(),
+            axes: snapshot.axes.clone(),
+        }
+    }
+
+    /// How far a button is pressed, 0 to 1.
+    pub fn button_value(&self, button: GamepadButton) -> f32 {
+        self.buttons.get(button.index() as usize).copied().unwrap_or(0.0)
+    }
+
+    /// -1 to 1 with `deadzone` taken out. Stick axes use a round deadzone shared with their partner, so diagonals
+    /// aren't cut off.
+    pub fn axis(&self, axis: GamepadAxis, deadzone: f32) -> f32 {
+        let raw = |axis: GamepadAxis| self.axes.get(axis.index() as usize).copied().unwrap_or(0.0);
+        match axis.partner().filter(|_| self.standard) {
+            Some(partner) => {
+                let stick = match axis {
+                    GamepadAxis::LeftX | GamepadAxis::RightX => vec2(raw(axis), raw(partner)),
+                    _ => vec2(raw(partner), raw(axis)),
+                };
+                let stick = radial_deadzone(stick, deadzone);
+                match axis {
+                    GamepadAxis::LeftX | GamepadAxis::RightX => stick.x,
+                    _ => stick.y,
+                }
+            }
+            None => {
+                let value = raw(axis);
+                radial_deadzone(vec2(value, 0.0), deadzone).x
+            }
+        }
+    }
+}
+
+/// Zero inside `deadzone`, then rescaled so the output still goes smoothly from 0 to 1.
+pub fn radial_deadzone(stick: Vector2<f32>, deadzone: f32) -> Vector2<f32> {
+    let length = stick.magnitude();
+    if length <= deadzone || length == 0.0 {
+        return vec2(0.0, 0.0);
+    }
+    let scaled = ((length - deadzone) / (1.0 - deadzone)).min(1.0);
+    stick * (scaled / length)
+}
+
+/// Read every connected pad. Needs polling each frame, the browser doesn't send events for button presses.
+pub fn read_gamepads() -> Vec<GamepadSnapshot> {
+    let pads = match web_sys::window().and_then(|window| window.navigator().get_gamepads().ok()) {
+        Some(pads) => pads,
+        None => return Vec::new(),
+    };
+
+    pads.iter()
+        .filter_map(|pad| pad.dyn_into::<web_sys::Gamepad>().ok())
+        .filter(|pad| pad.connected())
+        .map(|pad| GamepadSnapshot {
+            index: pad.index(),
+            name: pad.id(),
+            standard: pad.mapping() == GamepadMappingType::Standard,
+            buttons: pad.buttons().iter()
+                .map(|button| button.unchecked_into::<web_sys::GamepadButton>())
+                .map(|button| (button.pressed(), button.value() as f32))
+                .collect(),
+            axes: pad.axes().iter().map(|axis| axis.as_f64().unwrap_or(0.0) as f32).collect(),
+        })
+        .collect()
+}
+
+/// Shake pad `index` for `duration` seconds. `strong` and `weak` are the two motors, 0 to 1. Does nothing on
+/// browsers or pads without `vibrationActuator`.
+pub fn rumble(index: u32, strong: f32, weak: f32, duration: f64) {
+    let pad = web_sys::window()
+        .and_then(|window| window.navigator().get_gamepads().ok())
+        .map(|pads| pads.get(index))
+        .and_then(|pad| pad.dyn_into::<web_sys::Gamepad>().ok());
+    let actuator = pad.and_then(|pad| js_sys::Reflect::get(&pad, &JsValue::from_str("vibrationActuator")).ok())
+        .filter(|actuator| actuator.is_object());
+    let actuator = match actuator {
+        Some(actuator) => actuator,
+        None => return,
+    };
+
+    let params = js_sys::Object::new();
+    let set = |key: &str, value: f64| js_sys::Reflect::set(&params, &JsValue::from_str(key), &JsValue::from_f64(value));
+    let _ = set("duration", duration * 1000.0);
+    let _ = set("strongMagnitude", strong.clamp(0.0, 1.0) as f64);
+    let _ = set("weakMagnitude", weak.clamp(0.0, 1.0) as f64);
+
+    let play_effect = js_sys::Reflect::get(&actuator, &JsValue::from_str("playEffect")).ok()
+        .and_then(|play| play.dyn_into::<js_sys::Function>().ok());
+    if let Some(play_effect) = play_effect {
+        let _ = play_effect.call2(&actuator, &JsValue::from_str("dual-rumble"), &params);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::vec2;
+
+    use crate::gamepad::{Gamepad, GamepadAxis, GamepadButton, GamepadSnapshot, radial_deadzone};
+
+    #[test]
+    fn standard_names_round_trip() {
+        for index in 0..20 {
+            assert_eq!(GamepadButton::from_index(index, true).index(), index);
+            assert_eq!(GamepadAxis::from_index(index, true).index(), index);
+        }
+        assert_eq!(GamepadButton::from_index(0, true), GamepadButton::South);
+        assert_eq!(GamepadButton::from_index(0, false), GamepadButton::Other(0));
+        assert_eq!(GamepadAxis::from_index(3, true), GamepadAxis::RightY);
+    }
+
+    #[test]
+    fn deadzone_removes_drift_and_rescales() {
+        assert_eq!(radial_deadzone(vec2(0.1, -0.05), 0.2), vec2(0.0, 0.0));
+        assert_eq!(radial_deadzone(vec2(1.0, 0.0), 0.2), vec2(1.0, 0.0));
+        let half = radial_deadzone(vec2(0.6, 0.0), 0.2);
+        assert!((half.x - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stick_deadzone_is_round() {
+        let pad = Gamepad::new(&GamepadSnapshot {
+            index: 0,
+            name: String::from("pad"),
+            standard: true,
+            buttons: vec![(true, 1.0)],
+            axes: vec![0.15, 0.15, 0.0, 0.0],
+        });
+        // Each axis alone is inside the deadzone, but together they're outside it.
+        assert!(pad.axis(GamepadAxis::LeftX, 0.2) > 0.0);
+        assert!(pad.axis(GamepadAxis::LeftY, 0.2) > 0.0);
+        assert_eq!(pad.axis(GamepadAxis::RightX, 0.2), 0.0);
+        assert_eq!(pad.button_value(GamepadButton::South), 1.0);
+        assert_eq!(pad.button_value(GamepadButton::East), 0.0);
+    }
+}
@@ -0,0 +1,252 @@
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{HtmlCanvasElement, OesVertexArrayObject, WebGl2RenderingContext, WebGlActiveInfo, WebGlBuffer,
+              WebGlFramebuffer, WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlTexture, WebGlUniformLocation,
+              WebGlVertexArrayObject};
+
+use crate::error::{LagomError, ShaderStage};
+
+/// A WebGL2 context, or a WebGL1 one on devices without it. Everything the renderer needs exists in both with the
+/// same constants, so keep using `WebGl2RenderingContext::` for those.
+pub enum Gl {
+    WebGl2(WebGl2RenderingContext),
+    /// With `OES_vertex_array_object` if the device has it.
+    WebGl1(WebGlRenderingContext, Option<OesVertexArrayObject>),
+}
+
+/// Forward calls to whichever context we have.
+macro_rules! delegate {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)*) => {
+        $(
+            #[allow(clippy::too_many_arguments)]
+            pub fn $name(&self $(, $arg: $ty)*) $(-> $ret)? {
+                match self {
+                    Gl::WebGl2(gl) => gl.$name($($arg),*),
+                    Gl::WebGl1(gl, _) => gl.$name($($arg),*),
+                }
+            }
+        )*
+    };
+}
+
+impl Gl {
+    /// Get a WebGL2 context from the canvas, falling back to WebGL1.
+    pub fn from_canvas(canvas: &HtmlCanvasElement) -> Result<Self, LagomError> {
+        let context = |kind: &str| canvas.get_context(kind).ok().flatten();
+
+        if let Some(gl) = context("webgl2").and_then(|gl| gl.dyn_into::<WebGl2RenderingContext>().ok()) {
+            return Ok(Gl::WebGl2(gl));
+        }
+
+        let gl = context("webgl")
+            .and_then(|gl| gl.dyn_into::<WebGlRenderingContext>().ok())
+            .ok_or_else(|| LagomError::ContextUnavailable(String::from("webgl2 or webgl")))?;
+        let vertex_arrays = gl.get_extension("OES_vertex_array_object").ok().flatten()
+            .map(|extension| extension.unchecked_into::<OesVertexArrayObject>());
+        Ok(Gl::WebGl1(gl, vertex_arrays))
+    }
+
+    pub fn is_webgl1(&self) -> bool {
+        matches!(self, Gl::WebGl1(..))
+    }
+
+    /// `None` on WebGL1 without `OES_vertex_array_object`, the attributes have to be set up on every bind instead.
+    pub fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+        match self {
+            Gl::WebGl2(gl) => gl.create_vertex_array(),
+            Gl::WebGl1(_, extension) => extension.as_ref().and_then(|extension| extension.create_vertex_array_oes()),
+        }
+    }
+
+    pub fn bind_vertex_array(&self, array: Option<&WebGlVertexArrayObject>) {
+        match self {
+            Gl::WebGl2(gl) => gl.bind_vertex_array(array),
+            Gl::WebGl1(_, Some(extension)) => extension.bind_vertex_array_oes(array),
+            Gl::WebGl1(_, None) => {}
+        }
+    }
+
+    /// Shaders are written as GLSL ES 3.0, on WebGL1 they are translated down to GLSL ES 1.0 first.
+    pub fn shader_source(&self, shader: &WebGlShader, source: &str, stage: ShaderStage) {
+        match self {
+            Gl::WebGl2(gl) => gl.shader_source(shader, source),
+            Gl::WebGl1(gl, _) => gl.shader_source(shader, &glsl_100(source, stage)),
+        }
+    }
+
+    delegate! {
+        fn active_texture(&self, texture: u32);
+        fn attach_shader(&self, program: &WebGlProgram, shader: &WebGlShader);
+        fn bind_attrib_location(&self, program: &WebGlProgram, index: u32, name: &str);
+        fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>);
+        fn bind_framebuffer(&self, target: u32, framebuffer: Option<&WebGlFramebuffer>);
+        fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>);
+        fn blend_func(&self, source: u32, destination: u32);
+        fn buffer_data_with_array_buffer_view(&self, target: u32, data: &js_sys::Object, usage: u32);
+        fn clear(&self, mask: u32);
+        fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+        fn compile_shader(&self, shader: &WebGlShader);
+        fn create_buffer(&self) -> Option<WebGlBuffer>;
+        fn create_framebuffer(&self) -> Option<WebGlFramebuffer>;
+        fn create_program(&self) -> Option<WebGlProgram>;
+        fn create_shader(&self, shader_type: u32) -> Option<WebGlShader>;
+        fn create_texture(&self) -> Option<WebGlTexture>;
+        fn delete_program(&self, program: Option<&WebGlProgram>);
+        fn delete_shader(&self, shader: Option<&WebGlShader>);
+        fn delete_texture(&self, texture: Option<&WebGlTexture>);
+        fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+        fn enable(&self, capability: u32);
+        fn enable_vertex_attrib_array(&self, index: u32);
+        fn framebuffer_texture_2d(&self, target: u32, attachment: u32, texture_target: u32, texture: Option<&WebGlTexture>, level: i32);
+        fn generate_mipmap(&self, target: u32);
+        fn get_active_uniform(&self, program: &WebGlProgram, index: u32) -> Option<WebGlActiveInfo>;
+        fn get_parameter(&self, name: u32) -> Result<JsValue, JsValue>;
+        fn get_program_info_log(&self, program: &WebGlProgram) -> Option<String>;
+        fn get_program_parameter(&self, program: &WebGlProgram, name: u32) -> JsValue;
+        fn get_shader_info_log(&self, shader: &WebGlShader) -> Option<String>;
+        fn get_shader_parameter(&self, shader: &WebGlShader, name: u32) -> JsValue;
+        fn get_uniform_location(&self, program: &WebGlProgram, name: &str) -> Option<WebGlUniformLocation>;
+        fn link_program(&self, program: &WebGlProgram);
+        fn read_pixels_with_opt_u8_array(&self, x: i32, y: i32, width: i32, height: i32, format: u32, pixel_type: u32, pixels: Option<&mut [u8]>) -> Result<(), JsValue>;
+        fn tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(&self, target: u32, level: i32, internal_format: i32, width: i32, height: i32, border: i32, format: u32, pixel_type: u32, pixels: Option<&[u8]>) -> Result<(), JsValue>;
+        fn tex_parameteri(&self, target: u32, name: u32, value: i32);
+        fn uniform1f(&self, location: Option<&WebGlUniformLocation>, value: f32);
+        fn uniform1i(&self, location: Option<&WebGlUniformLocation>, value: i32);
+        fn uniform2fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, value: &[f32]);
+        fn uniform3fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, value: &[f32]);
+        fn uniform4fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, value: &[f32]);
+        fn uniform_matrix4fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, transpose: bool, value: &[f32]);
+        fn use_program(&self, program: Option<&WebGlProgram>);
+        fn vertex_attrib_pointer_with_i32(&self, index: u32, size: i32, data_type: u32, normalized: bool, stride: i32, offset: i32);
+        fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    }
+}
+
+/// Rewrite a GLSL ES 3.0 shader as GLSL ES 1.0. Covers what the built in shaders use: `in`/`out` declarations,
+/// `texture()`, a single fragment output and `highp` fragment precision. Lines stay where they were, or are put back
+/// with `#line`, so compile errors point at the original.
+pub fn glsl_100(source: &str, stage: ShaderStage) -> String {
+    let mut output = None;
+    let mut lines = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        let highp = match stage {
+            ShaderStage::Fragment => trimmed.strip_prefix("precision highp "),
+            ShaderStage::Vertex => None,
+        };
+
+        let translated = if trimmed.starts_with("#version") {
+            String::new()
+        } else if let Some(rest) = highp {
+            // Fragment shaders don't have to support highp in GLSL ES 1.0, and the devices stuck on WebGL1 often
+            // don't. In 1.0 `#line n` makes the next line n + 1.
+            [
+                "#ifdef GL_FRAGMENT_PRECISION_HIGH".to_string(),
+                format!("{}precision highp {}", indent, rest),
+                "#else".to_string(),
+                format!("{}precision mediump {}", indent, rest),
+                "#endif".to_string(),
+                format!("#line {}", index + 1),
+            ].join("\n")
+        } else if let Some(rest) = trimmed.strip_prefix("in ") {
+            match stage {
+                ShaderStage::Vertex => format!("{}attribute {}", indent, rest),
+                ShaderStage::Fragment => format!("{}varying {}", indent, rest),
+            }
+        } else if let Some(rest) = trimmed.strip_prefix("out ") {
+            match stage {
+                ShaderStage::Vertex => format!("{}varying {}", indent, rest),
+                ShaderStage::Fragment => {
+                    // `out vec4 outColor;` becomes `gl_FragColor`.
+                    output = rest.trim_end_matches(';').split_whitespace().last().map(String::from);
+                    String::new()
+                }
+            }
+        } else {
+            line.to_string()
+        };
+        lines.push(translated);
+    }
+
+    let mut translated = lines.join("\n");
+    translated = replace_identifier(&translated, "texture", "texture2D");
+    if let Some(output) = output {
+        translated = replace_identifier(&translated, &output, "gl_FragColor");
+    }
+    translated
+}
+
+/// Replace whole identifiers only, so `texture` doesn't touch `u_texture` or `v_texcoord`.
+fn replace_identifier(source: &str, from: &str, to: &str) -> String {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(index) = rest.find(from) {
+        let before = rest[..index].chars().last();
+        let after = rest[index + from.len()..].chars().next();
+        result.push_str(&rest[..index]);
+        if !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier) {
+            result.push_str(to);
+        } else {
+            result.push_str(from);
+        }
+        rest = &rest[index + from.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::ShaderStage;
+    use crate::gl::glsl_100;
+    use crate::material::{OUTLINE_FRAGMENT_SHADER, SPRITE_FRAGMENT_SHADER, SPRITE_VERTEX_SHADER};
+
+    #[test]
+    fn vertex_shader_uses_attributes_and_varyings() {
+        let source = glsl_100(SPRITE_VERTEX_SHADER, ShaderStage::Vertex);
+        assert!(!source.contains("#version"));
+        assert!(source.contains("attribute vec2 a_position;"));
+        assert!(source.contains("varying vec2 v_texcoord;"));
+        assert_eq!(source.lines().count(), SPRITE_VERTEX_SHADER.lines().count());
+    }
+
+    #[test]
+    fn fragment_shader_writes_gl_frag_color() {
+        let source = glsl_100(SPRITE_FRAGMENT_SHADER, ShaderStage::Fragment);
+        assert!(source.contains("varying vec2 v_texcoord;"));
+        assert!(source.contains("gl_FragColor = texture2D(u_texture, v_texcoord) * v_color;"));
+        assert!(!source.contains("outColor"));
+    }
+
+    #[test]
+    fn only_whole_identifiers_are_replaced() {
+        let source = glsl_100(OUTLINE_FRAGMENT_SHADER, ShaderStage::Fragment);
+        assert!(source.contains("uniform sampler2D u_texture;"));
+        assert!(source.contains("texture2D(u_texture, v_texcoord + vec2(u_texel_size.x, 0.0)).a"));
+        assert!(!source.contains("texture("));
+    }
+
+    #[test]
+    fn fragment_precision_falls_back_to_mediump() {
+        let source = glsl_100("#version 300 es\nprecision highp float;\nout vec4 outColor;\nvoid main() {}", ShaderStage::Fragment);
+        let lines: Vec<&str> = source.lines().collect();
+        assert_eq!(lines, vec![
+            "",
+            "#ifdef GL_FRAGMENT_PRECISION_HIGH",
+            "precision highp float;",
+            "#else",
+            "precision mediump float;",
+            "#endif",
+            "#line 2",
+            "",
+            "void main() {}",
+        ]);
+
+        let vertex = glsl_100("#version 300 es\nprecision highp float;", ShaderStage::Vertex);
+        assert_eq!(vertex, "\nprecision highp float;");
+    }
+}
@@ -0,0 +1,149 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{MessageEvent, WebSocket};
+
+use crate::assets::{fetch_version, Handle};
+use crate::error::LagomError;
+
+/// How to find out that asset files changed, for development.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HotReload {
+    /// Ask the server about every loaded asset each `interval` seconds. Needs it to send `ETag` or `Last-Modified`
+    /// headers, which most static file servers do.
+    Poll { interval: f64 },
+    /// Listen on a WebSocket, e.g. from a file watching dev server, for messages naming paths that changed.
+    WebSocket(String),
+}
+
+/// A socket from a dev server and its message handler, which has to live as long as it.
+type Socket = (WebSocket, Closure<dyn FnMut(MessageEvent)>);
+
+/// Collects changes found by polling or from the socket until the asset server picks them up.
+pub struct Watcher {
+    interval: Option<f64>,
+    /// When to next poll, in milliseconds since the epoch.
+    next_poll: f64,
+    polls_in_flight: Rc<Cell<usize>>,
+    versions: Rc<RefCell<Vec<(Handle, String)>>>,
+    changed: Rc<RefCell<Vec<String>>>,
+    socket: Option<Socket>,
+}
+
+impl Watcher {
+    pub fn new(mode: HotReload) -> Result<Self, LagomError> {
+        let mut watcher = Self {
+            interval: None,
+            next_poll: 0.0,
+            polls_in_flight: Rc::new(Cell::new(0)),
+            versions: Rc::new(RefCell::new(Vec::new())),
+            changed: Rc::new(RefCell::new(Vec::new())),
+            socket: None,
+        };
+
+        match mode {
+            HotReload::Poll { interval } => watcher.interval = Some(interval.max(0.1)),
+            HotReload::WebSocket(url) => {
+                let socket = WebSocket::new(&url)
+                    .map_err(|e| LagomError::AssetFetch(format!("couldn't connect to {}: {:?}", url, e)))?;
+                let changed = watcher.changed.clone();
+                let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+                    if let Some(path) = event.data().as_string() {
+                        changed.borrow_mut().extend(path.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()));
+                    }
+                }) as Box<dyn FnMut(MessageEvent)>);
+                socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+                watcher.socket = Some((socket, on_message));
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Ask for the current version of each of `assets` if it's time to poll and the last round has finished.
+    pub fn poll(&mut self, assets: Vec<(Handle, String)>) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let now = js_sys::Date::now();
+        if now < self.next_poll || self.polls_in_flight.get() > 0 {
+            return;
+        }
+        self.next_poll = now + interval * 1000.0;
+
+        for (handle, path) in assets {
+            let (in_flight, versions) = (self.polls_in_flight.clone(), self.versions.clone());
+            in_flight.set(in_flight.get() + 1);
+            wasm_bindgen_futures::spawn_local(async move {
+                // A failed poll just tries again next time.
+                if let Ok(Some(version)) = fetch_version(&path).await {
+                    versions.borrow_mut().push((handle, version));
+                }
+                in_flight.set(in_flight.get() - 1);
+            });
+        }
+    }
+
+    /// Versions from polling since the last call.
+    pub fn take_versions(&self) -> Vec<(Handle, String)> {
+        self.versions.borrow_mut().drain(..).collect()
+    }
+
+    /// Paths the socket said changed since the last call.
+    pub fn take_changed(&self) -> Vec<String> {
+        self.changed.borrow_mut().drain(..).collect()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some((socket, _)) = &self.socket {
+            socket.set_onmessage(None);
+            let _ = socket.close();
+        }
+    }
+}
+
+/// Whether a changed path from a dev server refers to an asset loaded from `asset`. Relative paths are from the
+/// server root, so they have to match in full. An absolute path can have more in front, e.g. a file's location on disk
+/// or the directory the game is served from.
+pub fn matches_path(asset: &str, changed: &str) -> bool {
+    let clean = |path: &str| {
+        let path = path.trim_start_matches("./").replace('\\', "/");
+        let absolute = path.starts_with('/') || path.get(1..3) == Some(":/");
+        (path.trim_start_matches('/').to_string(), absolute)
+    };
+    let ((asset, asset_absolute), (changed, changed_absolute)) = (clean(asset), clean(changed));
+    if asset.is_empty() || changed.is_empty() {
+        return false;
+    }
+
+    let ends_with = |long: &str, short: &str| {
+        long == short || (long.ends_with(short) && long[..long.len() - short.len()].ends_with('/'))
+    };
+    asset == changed
+        || (changed_absolute && ends_with(&changed, &asset))
+        || (asset_absolute && ends_with(&asset, &changed))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hot_reload::matches_path;
+
+    #[test]
+    fn changed_paths_match_assets() {
+        assert!(matches_path("assets/player.png", "assets/player.png"));
+        assert!(matches_path("assets/player.png", "/assets/player.png"));
+        assert!(matches_path("./assets/player.png", "/home/me/game/assets/player.png"));
+        assert!(matches_path("assets/player.png", "C:\\game\\assets\\player.png"));
+        assert!(matches_path("/static/assets/player.png", "assets/player.png"));
+
+        assert!(!matches_path("assets/player.png", "assets/enemy.png"));
+        assert!(!matches_path("assets/player.png", "assets/big_player.png"));
+        assert!(!matches_path("assets/player.png", ""));
+        assert!(!matches_path("player.png", "enemies/player.png"));
+        assert!(!matches_path("enemies/player.png", "player.png"));
+    }
+}
@@ -0,0 +1,612 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use cgmath::{Vector2, vec2};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{HtmlCanvasElement, KeyboardEvent, MouseEvent, TouchEvent, WheelEvent};
+
+use crate::camera::Camera2D;
+use crate::event::Event;
+use crate::gamepad::{Gamepad, GamepadAxis, GamepadButton, GamepadSnapshot};
+use crate::scaling::Screen;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Other(i16),
+}
+
+impl MouseButton {
+    /// From `MouseEvent.button`.
+    fn from_dom(button: i16) -> Self {
+        match button {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            other => MouseButton::Other(other),
+        }
+    }
+}
+
+/// Anything that can be held down.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    /// A physical key by its `KeyboardEvent.code`, e.g. "KeyW", "ArrowLeft" or "Space". Doesn't change with the
+    /// keyboard layout, so WASD stays in the same place on AZERTY.
+    Key(String),
+    Mouse(MouseButton),
+    /// A button on the pad with this index.
+    Gamepad(u32, GamepadButton),
+}
+
+impl Button {
+    pub fn key(code: &str) -> Self {
+        Button::Key(code.to_string())
+    }
+}
+
+/// Raw input as it arrives from the page, positions in CSS pixels relative to the canvas.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    ButtonDown(Button),
+    ButtonUp(Button),
+    MouseMove { x: f64, y: f64 },
+    Wheel { x: f64, y: f64 },
+    TouchStart { id: i32, x: f64, y: f64 },
+    TouchMove { id: i32, x: f64, y: f64 },
+    TouchEnd { id: i32 },
+    /// The page lost focus, key up events won't arrive so everything is let go.
+    Blur,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Touch {
+    /// Stays the same for as long as the finger is down.
+    pub id: i32,
+    /// In game pixels, like the mouse.
+    pub position: Vector2<f32>,
+    pub just_started: bool,
+}
+
+/// Stick movement smaller than this is ignored, cheap sticks don't center exactly.
+const DEFAULT_DEADZONE: f32 = 0.15;
+
+/// The state of the keyboard, mouse, touch screen and gamepads for the current frame.
+pub struct Input {
+    down: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    /// Presses and releases from frames that ran no fixed step, kept for the next one.
+    unstepped: (HashSet<Button>, HashSet<Button>),
+    /// The frame's own presses and releases, put aside while fixed steps see theirs.
+    frame_edges: Option<(HashSet<Button>, HashSet<Button>)>,
+    /// Whether a fixed step has run since the last `process`.
+    stepped: bool,
+    mouse: Vector2<f32>,
+    wheel: Vector2<f32>,
+    touches: Vec<Touch>,
+    /// Indexed by the browser's pad index, `None` for gaps.
+    gamepads: Vec<Option<Gamepad>>,
+    deadzone: f32,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            unstepped: (HashSet::new(), HashSet::new()),
+            frame_edges: None,
+            stepped: false,
+            mouse: vec2(0.0, 0.0),
+            wheel: vec2(0.0, 0.0),
+            touches: Vec::new(),
+            gamepads: Vec::new(),
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+impl Input {
+    /// Start a new frame: forget what was pressed and released last frame, then apply everything that happened since.
+    pub fn process(&mut self, events: impl IntoIterator<Item=InputEvent>, screen: &Screen) {
+        if !self.stepped {
+            self.unstepped.0.extend(self.pressed.drain());
+            self.unstepped.1.extend(self.released.drain());
+        }
+        self.stepped = false;
+        self.pressed.clear();
+        self.released.clear();
+        self.wheel = vec2(0.0, 0.0);
+        for touch in &mut self.touches {
+            touch.just_started = false;
+        }
+
+        for event in events {
+            match event {
+                InputEvent::ButtonDown(button) => {
+                    // Held keys repeat, only the first counts as a press.
+                    if self.down.insert(button.clone()) {
+                        self.pressed.insert(button);
+                    }
+                }
+                InputEvent::ButtonUp(button) => {
+                    if self.down.remove(&button) {
+                        self.released.insert(button);
+                    }
+                }
+                InputEvent::MouseMove { x, y } => self.mouse = to_game(screen, x, y),
+                InputEvent::Wheel { x, y } => self.wheel += vec2(x as f32, y as f32),
+                InputEvent::TouchStart { id, x, y } => {
+                    self.touches.retain(|touch| touch.id != id);
+                    self.touches.push(Touch { id, position: to_game(screen, x, y), just_started: true });
+                }
+                InputEvent::TouchMove { id, x, y } => {
+                    if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == id) {
+                        touch.position = to_game(screen, x, y);
+                    }
+                }
+                InputEvent::TouchEnd { id } => self.touches.retain(|touch| touch.id != id),
+                InputEvent::Blur => {
+                    // Pads are still polled while the page is in the background, so only keys and mouse buttons
+                    // are lost.
+                    let (pads, lost) = self.down.drain().partition(|button| matches!(button, Button::Gamepad(..)));
+                    self.down = pads;
+                    self.released.extend(lost);
+                    self.touches.clear();
+                }
+            }
+        }
+    }
+
+    /// Take this frame's gamepad state, after `process`. Buttons go through the same pressed and released tracking
+    /// as keys. Returns connect and disconnect events.
+    pub fn poll_gamepads(&mut self, snapshots: &[GamepadSnapshot]) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        for (index, slot) in self.gamepads.iter_mut().enumerate() {
+            if slot.is_some() && !snapshots.iter().any(|pad| pad.index == index as u32) {
+                *slot = None;
+                let released: Vec<Button> = self.down.iter()
+                    .filter(|button| matches!(button, Button::Gamepad(pad, _) if *pad == index as u32))
+                    .cloned()
+                    .collect();
+                for button in released {
+                    self.down.remove(&button);
+                    self.released.insert(button);
+                }
+                events.push(Event::GamepadDisconnected { index: index as u32 });
+            }
+        }
+
+        for snapshot in snapshots {
+            let index = snapshot.index as usize;
+            if self.gamepads.len() <= index {
+                self.gamepads.resize(index + 1, None);
+            }
+            if self.gamepads[index].is_none() {
+                events.push(Event::GamepadConnected { index: snapshot.index, name: snapshot.name.clone() });
+            }
+            self.gamepads[index] = Some(Gamepad::new(snapshot));
+
+            for (button_index, (pressed, _)) in snapshot.buttons.iter().enumerate() {
+                let button = Button::Gamepad(snapshot.index, GamepadButton::from_index(button_index as u32, snapshot.standard));
+                if *pressed {
+                    if self.down.insert(button.clone()) {
+                        self.pressed.insert(button);
+                    }
+                } else if self.down.remove(&button) {
+                    self.released.insert(button);
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Switch to what the next fixed step should see. Fixed steps don't line up with frames, so the first one after
+    /// a press sees it even if it came frames ago, and later steps in the same frame don't see it again.
+    pub fn begin_fixed_step(&mut self) {
+        if self.frame_edges.is_none() {
+            self.frame_edges = Some((self.pressed.clone(), self.released.clone()));
+        }
+        if self.stepped {
+            self.pressed.clear();
+            self.released.clear();
+        } else {
+            let (pressed, released) = std::mem::take(&mut self.unstepped);
+            self.pressed.extend(pressed);
+            self.released.extend(released);
+            self.stepped = true;
+        }
+    }
+
+    /// Go back to the frame's presses and releases for the variable rate update.
+    pub fn end_fixed_steps(&mut self) {
+        if let Some((pressed, released)) = self.frame_edges.take() {
+            self.pressed = pressed;
+            self.released = released;
+        }
+    }
+
+    pub fn is_down(&self, button: &Button) -> bool {
+        self.down.contains(button)
+    }
+
+    /// Went down this frame, or during a fixed step, since the last one.
+    pub fn just_pressed(&self, button: &Button) -> bool {
+        self.pressed.contains(button)
+    }
+
+    /// Came up this frame, or during a fixed step, since the last one.
+    pub fn just_released(&self, button: &Button) -> bool {
+        self.released.contains(button)
+    }
+
+    /// Everything that went down this frame, e.g. to pick a new binding.
+    pub fn pressed_buttons(&self) -> impl Iterator<Item=&Button> {
+        self.pressed.iter()
+    }
+
+    pub fn is_key_down(&self, code: &str) -> bool {
+        self.is_down(&Button::key(code))
+    }
+
+    /// Mouse position on the canvas, in game pixels.
+    pub fn mouse_position(&self) -> Vector2<f32> {
+        self.mouse
+    }
+
+    /// Mouse position in the world, seen through `camera`.
+    pub fn mouse_world(&self, camera: &Camera2D) -> Vector2<f32> {
+        camera.screen_to_world(self.mouse)
+    }
+
+    /// How far the wheel moved this frame. Positive y is scrolling down.
+    pub fn wheel(&self) -> Vector2<f32> {
+        self.wheel
+    }
+
+    /// A connected pad by index.
+    pub fn gamepad(&self, index: u32) -> Option<&Gamepad> {
+        self.gamepads.get(index as usize).and_then(Option::as_ref)
+    }
+
+    /// Indices of the connected pads.
+    pub fn gamepads(&self) -> impl Iterator<Item=u32> + '_ {
+        self.gamepads.iter().enumerate().filter(|(_, pad)| pad.is_some()).map(|(index, _)| index as u32)
+    }
+
+    /// A stick axis on pad `index`, -1 to 1 with the deadzone taken out. 0 if the pad isn't connected.
+    pub fn gamepad_axis(&self, index: u32, axis: GamepadAxis) -> f32 {
+        self.gamepad(index).map_or(0.0, |pad| pad.axis(axis, self.deadzone))
+    }
+
+    /// How far a button on pad `index` is pressed, 0 to 1, for analog triggers.
+    pub fn gamepad_button_value(&self, index: u32, button: GamepadButton) -> f32 {
+        self.gamepad(index).map_or(0.0, |pad| pad.button_value(button))
+    }
+
+    /// Stick movement smaller than this, 0 to 1, reads as 0.
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone = deadzone.clamp(0.0, 0.99);
+    }
+
+    /// Fingers currently on the screen.
+    pub fn touches(&self) -> &[Touch] {
+        &self.touches
+    }
+}
+
+fn to_game(screen: &Screen, x: f64, y: f64) -> Vector2<f32> {
+    let (x, y) = screen.css_to_logical(x, y);
+    vec2(x, y)
+}
+
+/// A DOM event listener that's removed again when dropped, so the closure isn't called after it's freed.
+pub struct EventListener {
+    target: web_sys::EventTarget,
+    name: &'static str,
+    closure: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+impl EventListener {
+    pub fn new(target: &web_sys::EventTarget, name: &'static str, handler: impl FnMut(web_sys::Event) + 'static) -> Self {
+        let closure = Closure::wrap(Box::new(handler) as Box<dyn FnMut(web_sys::Event)>);
+        let _ = target.add_event_listener_with_callback(name, closure.as_ref().unchecked_ref());
+        Self { target: target.clone(), name, closure }
+    }
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(self.name, self.closure.as_ref().unchecked_ref());
+    }
+}
+
+/// Keys the browser scrolls the page with, which shouldn't happen while they're playing the game. `target` is the tag
+/// name of the element the key went to, empty for the window or document. Keys typed into anything but the canvas or
+/// the page itself, e.g. an `<input>`, keep their default so the page's own controls still work.
+fn scrolls_page(code: &str, target: &str) -> bool {
+    matches!(target, "" | "CANVAS" | "BODY" | "HTML")
+        && matches!(code, "Space" | "ArrowUp" | "ArrowDown" | "ArrowLeft" | "ArrowRight" | "PageUp" | "PageDown")
+}
+
+/// Collects DOM events into a queue until the next frame picks them up. Keyboard events come from the window since
+/// the canvas only gets them when focused, the rest from the canvas. The listeners are removed when it's dropped.
+pub struct DomInput {
+    queue: Rc<RefCell<Vec<InputEvent>>>,
+    _listeners: Vec<EventListener>,
+}
+
+impl DomInput {
+    pub fn attach(canvas: &HtmlCanvasElement) -> Self {
+        let queue = Rc::new(RefCell::new(Vec::new()));
+        let mut listeners = Vec::new();
+        let window = web_sys::window();
+
+        let mut listen = |target: Option<&web_sys::EventTarget>, name: &'static str, handler: fn(web_sys::Event, &HtmlCanvasElement, &mut Vec<InputEvent>)| {
+            let queue = queue.clone();
+            let canvas = canvas.clone();
+            if let Some(target) = target {
+                listeners.push(EventListener::new(target, name, move |event| {
+                    handler(event, &canvas, &mut queue.borrow_mut());
+                }));
+            }
+        };
+
+        let window_target = window.as_ref().map(|window| window.as_ref());
+        let canvas_target = Some(canvas.as_ref());
+
+        listen(window_target, "keydown", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<KeyboardEvent>() {
+                let target = event.target().and_then(|target| target.dyn_into::<web_sys::Element>().ok());
+                if scrolls_page(&event.code(), &target.map(|element| element.tag_name()).unwrap_or_default()) {
+                    event.prevent_default();
+                }
+                queue.push(InputEvent::ButtonDown(Button::Key(event.code())));
+            }
+        });
+        listen(window_target, "keyup", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<KeyboardEvent>() {
+                queue.push(InputEvent::ButtonUp(Button::Key(event.code())));
+            }
+        });
+        listen(window_target, "blur", |_, _, queue| queue.push(InputEvent::Blur));
+
+        listen(canvas_target, "mousedown", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                queue.push(InputEvent::MouseMove { x: event.offset_x() as f64, y: event.offset_y() as f64 });
+                queue.push(InputEvent::ButtonDown(Button::Mouse(MouseButton::from_dom(event.button()))));
+            }
+        });
+        // On the window so letting go outside the canvas still counts.
+        listen(window_target, "mouseup", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                queue.push(InputEvent::ButtonUp(Button::Mouse(MouseButton::from_dom(event.button()))));
+            }
+        });
+        listen(canvas_target, "mousemove", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                queue.push(InputEvent::MouseMove { x: event.offset_x() as f64, y: event.offset_y() as f64 });
+            }
+        });
+        listen(canvas_target, "contextmenu", |event, _, _| event.prevent_default());
+        listen(canvas_target, "wheel", |event, _, queue| {
+            if let Some(event) = event.dyn_ref::<WheelEvent>() {
+                event.prevent_default();
+                queue.push(InputEvent::Wheel { x: event.delta_x(), y: event.delta_y() });
+            }
+        });
+
+        listen(canvas_target, "touchstart", |event, canvas, queue| {
+            touches(event, canvas, queue, |id, x, y| InputEvent::TouchStart { id, x, y })
+        });
+        listen(canvas_target, "touchmove", |event, canvas, queue| {
+            touches(event, canvas, queue, |id, x, y| InputEvent::TouchMove { id, x, y })
+        });
+        listen(canvas_target, "touchend", |event, canvas, queue| {
+            touches(event, canvas, queue, |id, _, _| InputEvent::TouchEnd { id })
+        });
+        listen(canvas_target, "touchcancel", |event, canvas, queue| {
+            touches(event, canvas, queue, |id, _, _| InputEvent::TouchEnd { id })
+        });
+
+        Self { queue, _listeners: listeners }
+    }
+
+    /// Everything that happened since the last call.
+    pub fn drain(&self) -> Vec<InputEvent> {
+        std::mem::take(&mut self.queue.borrow_mut())
+    }
+}
+
+/// Queue an event for each touch that changed, with positions relative to the canvas.
+fn touches(event: web_sys::Event, canvas: &HtmlCanvasElement, queue: &mut Vec<InputEvent>,
+           make: fn(i32, f64, f64) -> InputEvent) {
+    if let Some(event) = event.dyn_ref::<TouchEvent>() {
+        // Stop the page scrolling or zooming, and the browser making up mouse events.
+        event.prevent_default();
+
+        let bounds = canvas.get_bounding_client_rect();
+        let changed = event.changed_touches();
+        for index in 0..changed.length() {
+            if let Some(touch) = changed.get(index) {
+                let (x, y) = (touch.client_x() as f64 - bounds.left(), touch.client_y() as f64 - bounds.top());
+                queue.push(make(touch.identifier(), x, y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::vec2;
+
+    use crate::event::Event;
+    use crate::gamepad::{GamepadAxis, GamepadButton, GamepadSnapshot};
+    use crate::input::{Button, Input, InputEvent, MouseButton, scrolls_page};
+    use crate::scaling::{ScalePolicy, Screen};
+
+    fn screen() -> Screen {
+        Screen::new(ScalePolicy::Fixed, 320, 240)
+    }
+
+    #[test]
+    fn press_hold_release() {
+        let mut input = Input::default();
+        let space = Button::key("Space");
+
+        input.process(vec![InputEvent::ButtonDown(space.clone())], &screen());
+        assert!(input.is_down(&space) && input.just_pressed(&space));
+
+        // Key repeat doesn't press again.
+        input.process(vec![InputEvent::ButtonDown(space.clone())], &screen());
+        assert!(input.is_down(&space) && !input.just_pressed(&space));
+
+        input.process(vec![InputEvent::ButtonUp(space.clone())], &screen());
+        assert!(!input.is_down(&space) && input.just_released(&space));
+
+        input.process(vec![], &screen());
+        assert!(!input.just_released(&space));
+    }
+
+    #[test]
+    fn fixed_steps_see_each_press_once() {
+        let mut input = Input::default();
+        let space = Button::key("Space");
+
+        // A frame with no fixed step keeps the press for the next one.
+        input.process(vec![InputEvent::ButtonDown(space.clone())], &screen());
+        input.end_fixed_steps();
+        assert!(input.just_pressed(&space));
+
+        // The next frame runs two steps, only the first sees it. The frame itself has no new press.
+        input.process(vec![], &screen());
+        input.begin_fixed_step();
+        assert!(input.just_pressed(&space));
+        input.begin_fixed_step();
+        assert!(!input.just_pressed(&space) && input.is_down(&space));
+        input.end_fixed_steps();
+        assert!(!input.just_pressed(&space));
+
+        // A release in a frame with two steps also only fires once, and the frame still sees it.
+        input.process(vec![InputEvent::ButtonUp(space.clone())], &screen());
+        input.begin_fixed_step();
+        assert!(input.just_released(&space));
+        input.begin_fixed_step();
+        assert!(!input.just_released(&space));
+        input.end_fixed_steps();
+        assert!(input.just_released(&space));
+
+        input.process(vec![], &screen());
+        input.begin_fixed_step();
+        assert!(!input.just_released(&space));
+        input.end_fixed_steps();
+    }
+
+    #[test]
+    fn tap_within_one_frame_is_seen() {
+        let mut input = Input::default();
+        let left = Button::Mouse(MouseButton::Left);
+        input.process(vec![InputEvent::ButtonDown(left.clone()), InputEvent::ButtonUp(left.clone())], &screen());
+        assert!(input.just_pressed(&left) && input.just_released(&left) && !input.is_down(&left));
+    }
+
+    #[test]
+    fn blur_releases_everything() {
+        let mut input = Input::default();
+        input.process(vec![InputEvent::ButtonDown(Button::key("KeyW")), InputEvent::TouchStart { id: 1, x: 1.0, y: 1.0 }], &screen());
+        input.process(vec![InputEvent::Blur], &screen());
+        assert!(!input.is_key_down("KeyW"));
+        assert!(input.just_released(&Button::key("KeyW")));
+        assert!(input.touches().is_empty());
+    }
+
+    #[test]
+    fn blur_leaves_held_pad_buttons_alone() {
+        let mut input = Input::default();
+        let south = Button::Gamepad(0, GamepadButton::South);
+        input.process(vec![], &screen());
+        input.poll_gamepads(&[pad(0, true, 0.0)]);
+        input.process(vec![InputEvent::Blur], &screen());
+        input.poll_gamepads(&[pad(0, true, 0.0)]);
+        assert!(input.is_down(&south));
+        assert!(!input.just_pressed(&south) && !input.just_released(&south));
+    }
+
+    #[test]
+    fn keys_in_text_fields_keep_their_default() {
+        assert!(scrolls_page("Space", "CANVAS") && scrolls_page("ArrowDown", "BODY") && scrolls_page("PageUp", ""));
+        assert!(!scrolls_page("Space", "INPUT") && !scrolls_page("ArrowLeft", "TEXTAREA"));
+        assert!(!scrolls_page("KeyA", "CANVAS"));
+    }
+
+    #[test]
+    fn mouse_and_touches_are_in_game_pixels() {
+        let mut screen = Screen::new(ScalePolicy::Fit { width: 160, height: 120 }, 320, 240);
+        screen.resize(320.0, 240.0, 1.0);
+
+        let mut input = Input::default();
+        input.process(vec![
+            InputEvent::MouseMove { x: 100.0, y: 50.0 },
+            InputEvent::TouchStart { id: 3, x: 20.0, y: 40.0 },
+            InputEvent::TouchStart { id: 4, x: 0.0, y: 0.0 },
+        ], &screen);
+        assert_eq!(input.mouse_position(), vec2(50.0, 25.0));
+        assert_eq!(input.touches()[0].position, vec2(10.0, 20.0));
+        assert!(input.touches()[0].just_started);
+
+        input.process(vec![InputEvent::TouchMove { id: 3, x: 40.0, y: 40.0 }, InputEvent::TouchEnd { id: 4 }], &screen);
+        assert_eq!(input.touches().len(), 1);
+        assert_eq!(input.touches()[0].position, vec2(20.0, 20.0));
+        assert!(!input.touches()[0].just_started);
+    }
+
+    #[test]
+    fn wheel_adds_up_within_a_frame() {
+        let mut input = Input::default();
+        input.process(vec![InputEvent::Wheel { x: 0.0, y: 3.0 }, InputEvent::Wheel { x: 0.0, y: 2.0 }], &screen());
+        assert_eq!(input.wheel(), vec2(0.0, 5.0));
+        input.process(vec![], &screen());
+        assert_eq!(input.wheel(), vec2(0.0, 0.0));
+    }
+
+    fn pad(index: u32, south: bool, left_x: f32) -> GamepadSnapshot {
+        GamepadSnapshot {
+            index,
+            name: String::from("Xbox Controller"),
+            standard: true,
+            buttons: vec![(south, south as i32 as f32), (false, 0.0)],
+            axes: vec![left_x, 0.0, 0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn gamepads_connect_press_and_disconnect() {
+        let mut input = Input::default();
+        let south = Button::Gamepad(1, GamepadButton::South);
+
+        input.process(vec![], &screen());
+        let events = input.poll_gamepads(&[pad(1, true, 1.0)]);
+        assert_eq!(events, vec![Event::GamepadConnected { index: 1, name: String::from("Xbox Controller") }]);
+        assert!(input.just_pressed(&south) && input.is_down(&south));
+        assert_eq!(input.gamepad_axis(1, GamepadAxis::LeftX), 1.0);
+        assert_eq!(input.gamepads().collect::<Vec<_>>(), vec![1]);
+
+        input.process(vec![], &screen());
+        assert!(input.poll_gamepads(&[pad(1, true, 0.1)]).is_empty());
+        assert!(!input.just_pressed(&south) && input.is_down(&south));
+        assert_eq!(input.gamepad_axis(1, GamepadAxis::LeftX), 0.0);
+
+        input.process(vec![], &screen());
+        assert_eq!(input.poll_gamepads(&[]), vec![Event::GamepadDisconnected { index: 1 }]);
+        assert!(input.just_released(&south) && !input.is_down(&south));
+        assert_eq!(input.gamepad_axis(1, GamepadAxis::LeftX), 0.0);
+    }
+}
@@ -1,102 +1,848 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use cgmath::{Matrix4, SquareMatrix};
 use image::RgbaImage;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 use web_sys::console;
 
-use crate::renderer::{Renderer, Texture};
+use crate::actions::ActionMap;
+use crate::assets::{Asset, AssetKind, AssetServer, Handle};
+use crate::audio::Audio;
+use crate::batch::{DrawParams, Layer, LayerId};
+use crate::camera::Camera2D;
+use crate::error::LagomError;
+use crate::event::Event;
+use crate::game_loop::GameLoop;
+use crate::input::{DomInput, Input};
+use crate::material::{Material, MaterialId};
+use crate::post::PostEffect;
+use crate::renderer::{ContextState, RenderTargetId, Renderer, Texture, TextureError, TextureFilter, TextureOptions, TextureStats};
+use crate::scaling::{ScalePolicy, Screen};
+use crate::scene::{Scene, SceneStack, Transition};
+use crate::shapes::{Fill, Shape};
+use crate::text::{BitmapFont, Font, FontId, layout, TextStyle};
+use crate::ttf::{GlyphAtlas, TrueTypeFont};
 
-mod renderer;
-mod ecs;
+pub use crate::ecs::{ComponentSlice, Filter, HasComp, Position, TextRenderer, TextValue, World};
+pub use crate::game::Game;
+
+pub mod actions;
+pub mod assets;
+pub mod audio;
+pub mod batch;
+pub mod camera;
+#[cfg(feature = "demo")]
+mod demo;
+pub mod error;
+pub mod event;
+pub mod game;
+pub mod game_loop;
+pub mod gamepad;
+mod gl;
+pub mod hot_reload;
+pub mod input;
+pub mod material;
+pub mod post;
+pub mod renderer;
+pub mod scaling;
+pub mod scene;
+pub mod sfxr;
+pub mod shapes;
+pub mod text;
+pub mod ttf;
+pub(crate) mod ecs;
 mod ecs_archetypes;
 mod ecs_v3;
 
-const IMG: &[u8] = include_bytes!("../assets/bg_tileable.png");
-
-type UpdateFn = fn(&mut LagomGame, delta: f64);
-
-const update: UpdateFn = |game, delta| {
-    console::log_1(&format!("{}", delta).into());
-    game.draw(0, 4, 10);
-};
+/// Draws the loading screen, with the time since the last frame in seconds.
+pub type LoadingFn = fn(&mut LagomGame, delta: f64);
 
-#[wasm_bindgen(start)]
-pub fn start() -> Result<(), JsValue> {
-    let mut game = LagomGame::new(update);
-
-    let img = image::load_from_memory(IMG).unwrap();
-    let img = img.to_rgba8();
-    game.load_texture(img);
+/// Start `game` on the page's `canvas` element and run it every animation frame from then on.
+pub fn run(game: impl Game + 'static) -> Result<(), JsValue> {
+    let mut lagom = LagomGame::new()?;
+    let mut game = game;
+    game.init(&mut lagom);
 
     let f: Rc<RefCell<Option<Closure<dyn FnMut()>>>> = Rc::new(RefCell::new(None));
     let outer_f = f.clone();
 
-    let window = web_sys::window().unwrap();
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("No global window, not running in a browser?"))?;
     if let Some(perf) = window.performance() {
-        let mut prev_delta = perf.now();
-
         *outer_f.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-            let now = perf.now();
-            let delta = now - prev_delta;
-            prev_delta = now;
-
-            game.update(delta);
-            game.render_frame();
+            lagom.frame(&mut game, perf.now() / 1000.0);
 
-            window.request_animation_frame(f.borrow().as_ref().unwrap().as_ref().unchecked_ref())
-                .expect("Frame request failed");
+            if let Some(frame) = f.borrow().as_ref() {
+                if let Err(e) = window.request_animation_frame(frame.as_ref().unchecked_ref()) {
+                    console::error_1(&e);
+                }
+            }
         }) as Box<dyn FnMut()>))
     }
 
-    let window = web_sys::window().unwrap();
-    window.request_animation_frame(outer_f.borrow().as_ref().unwrap().as_ref().unchecked_ref())
-        .expect("Frame request failed");
+    if let Some(frame) = outer_f.borrow().as_ref() {
+        web_sys::window()
+            .ok_or_else(|| JsValue::from_str("No global window, not running in a browser?"))?
+            .request_animation_frame(frame.as_ref().unchecked_ref())?;
+    }
 
     Ok(())
 }
 
+enum DrawCommand {
+    Texture(u32, DrawParams),
+    Shape(Shape, DrawParams),
+}
+
+/// Where a texture's pixels come from, to upload it again after the context is lost.
+enum TextureSource {
+    Image(RgbaImage),
+    /// Ask the game's reload callback.
+    Reload,
+    FontAtlas(FontId),
+    RenderTarget(RenderTargetId),
+    /// The texture was unloaded and won't be restored.
+    Unloaded,
+}
 
-struct LagomGame {
+impl TextureSource {
+    /// Mark the texture unloaded, handing back the copy of its image if one was kept.
+    fn unload(&mut self) -> Option<RgbaImage> {
+        match std::mem::replace(self, TextureSource::Unloaded) {
+            TextureSource::Image(image) => Some(image),
+            _ => None,
+        }
+    }
+}
+
+/// Gets a texture's image again after the context was lost, given its ID.
+type TextureReloader = Box<dyn FnMut(u32) -> Option<RgbaImage>>;
+
+/// The default loading screen, a bar across the middle of the screen filling up as assets arrive.
+fn draw_loading_bar(game: &mut LagomGame, _delta: f64) {
+    let (width, height) = game.renderer.target_size();
+    let (bar_width, bar_height) = (width as f32 * 0.5, 12.0);
+    let params = DrawParams::at((width as f32 - bar_width) / 2.0, (height as f32 - bar_height) / 2.0);
+
+    game.draw_shape(Shape::Rect { width: bar_width, height: bar_height, fill: Fill::Outline(2.0) }, params.clone());
+    let filled = bar_width * game.assets.progress();
+    game.draw_shape(Shape::Rect { width: filled, height: bar_height, fill: Fill::Solid }, params);
+}
+
+pub struct LagomGame {
     renderer: Renderer,
-    textures: Vec<Texture>,
+    /// Indexed by texture ID. Unloaded textures leave a gap so the other IDs stay the same.
+    textures: Vec<Option<Texture>>,
+    /// How to rebuild each texture, indexed the same as `textures`.
+    texture_sources: Vec<(TextureSource, TextureOptions)>,
+    /// When set, loaded images aren't kept around and this is asked for them instead.
+    texture_reloader: Option<TextureReloader>,
+    fonts: Vec<Font>,
+    camera: Camera2D,
+    /// Events for the game to look at during the current update.
+    events: Vec<Event>,
+    input: Input,
+    dom_input: DomInput,
+    actions: ActionMap,
+    /// `None` if the browser has no Web Audio, the game runs silently.
+    audio: Option<Audio>,
+    assets: AssetServer,
+    /// Show the loading screen instead of updating until this has loaded.
+    loading: Option<Handle>,
+    loading_fn: LoadingFn,
+    scenes: SceneStack,
 
-    /// (Texture ID, x, y)
-    draw_buffer: Vec<(u32, u32, u32)>,
-    update_fn: UpdateFn,
+    /// (Render target, command). No render target means the scene.
+    draw_buffer: Vec<(Option<RenderTargetId>, DrawCommand)>,
+    /// Where `draw` currently sends things.
+    draw_target: Option<RenderTargetId>,
+    game_loop: GameLoop,
+    /// Interpolation alpha for the frame being drawn, see `alpha`.
+    alpha: f64,
 }
 
 impl LagomGame {
-    pub fn new(f: UpdateFn) -> Self {
-        let renderer = Renderer::new("canvas").unwrap();
-        Self { renderer, textures: Vec::new(), draw_buffer: Vec::new(), update_fn: f }
+    pub fn new() -> Result<Self, LagomError> {
+        let renderer = Renderer::new("canvas")?;
+        let (width, height) = renderer.target_size();
+        let camera = Camera2D::new(width as f32, height as f32);
+        let dom_input = DomInput::attach(renderer.canvas());
+        let audio = Audio::new()
+            .map_err(|e| console::error_1(&format!("Running without sound: {}", e).into()))
+            .ok();
+        Ok(Self {
+            renderer,
+            textures: Vec::new(),
+            texture_sources: Vec::new(),
+            texture_reloader: None,
+            fonts: Vec::new(),
+            camera,
+            events: Vec::new(),
+            input: Input::default(),
+            dom_input,
+            actions: ActionMap::new(),
+            audio,
+            assets: AssetServer::default(),
+            loading: None,
+            loading_fn: draw_loading_bar,
+            scenes: SceneStack::default(),
+            draw_buffer: Vec::new(),
+            draw_target: None,
+            game_loop: GameLoop::default(),
+            alpha: 0.0,
+        })
+    }
+
+    /// Run one frame of `game` starting at `now` seconds: its events, any fixed updates that are due, then the
+    /// variable rate update and drawing. Does nothing while the GL context is lost, and rebuilds everything when it
+    /// comes back.
+    pub fn frame(&mut self, game: &mut dyn Game, now: f64) {
+        match self.renderer.context_state() {
+            ContextState::Lost => {
+                self.game_loop.skip(now);
+                return;
+            }
+            ContextState::Restored => {
+                if let Err(e) = self.restore_context() {
+                    console::error_1(&e.into());
+                    self.game_loop.skip(now);
+                    return;
+                }
+            }
+            ContextState::Ready => {}
+        }
+
+        if self.renderer.poll_resize() {
+            self.sync_camera_viewport();
+            let (width, height) = self.renderer.target_size();
+            self.events.push(Event::Resized { width, height, pixel_ratio: self.renderer.screen().pixel_ratio });
+        }
+
+        self.read_input();
+        self.process_assets();
+
+        let events = std::mem::take(&mut self.events);
+        for event in &events {
+            game.on_event(self, event);
+        }
+        let pushed = std::mem::replace(&mut self.events, events);
+        self.events.extend(pushed);
+
+        let frame = self.game_loop.advance(now);
+        if let Some(handle) = self.loading {
+            if !self.assets.is_done(handle) {
+                (self.loading_fn)(self, frame.delta);
+                self.render_frame();
+                self.events.clear();
+                return;
+            }
+            self.loading = None;
+        }
+
+        self.advance_scenes(frame.delta);
+        for _ in 0..frame.steps {
+            self.input.begin_fixed_step();
+            game.fixed_update(self, frame.step);
+            self.fixed_update_scenes(frame.step);
+        }
+        self.input.end_fixed_steps();
+
+        self.alpha = frame.alpha;
+        game.update(self, frame.delta);
+        self.update_scenes(frame.delta);
+        self.camera.update(frame.delta as f32);
+        game.render(self);
+        self.render_frame();
+        self.events.clear();
+    }
+
+    pub fn set_update_rate(&mut self, updates_per_second: f64) {
+        self.game_loop.set_update_rate(updates_per_second);
+    }
+
+    /// How far the current frame is between the last fixed update and the next, 0 to 1. Draw things at
+    /// `previous + (current - previous) * alpha` to keep movement smooth.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// What happened since the last update, e.g. the screen being resized.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn screen(&self) -> &Screen {
+        self.renderer.screen()
+    }
+
+    /// Choose how the game follows the canvas' size on the page. Takes effect from the next frame, which reports
+    /// an `Event::Resized` if the game's size changed.
+    pub fn set_scale_policy(&mut self, policy: ScalePolicy) {
+        self.renderer.set_scale_policy(policy);
+    }
+
+    /// The camera covers the whole of whatever the scene is drawn to.
+    fn sync_camera_viewport(&mut self) {
+        let (width, height) = self.renderer.target_size();
+        self.camera.resize(width as f32, height as f32);
+    }
+
+    /// Recreate the renderer and upload every loaded texture again under the same ID.
+    fn restore_context(&mut self) -> Result<(), LagomError> {
+        self.renderer.restore()?;
+
+        for id in 0..self.textures.len() {
+            if self.textures[id].is_none() {
+                continue;
+            }
+
+            let (source, options) = &self.texture_sources[id];
+            let texture = match source {
+                TextureSource::Image(image) => self.renderer.load_texture_with(image.clone(), options),
+                TextureSource::Reload => match self.texture_reloader.as_mut().and_then(|reload| reload(id as u32)) {
+                    Some(image) => self.renderer.load_texture_with(image, options),
+                    None => Err(TextureError::NotLoaded(id as u32)),
+                },
+                TextureSource::FontAtlas(font) => match &self.fonts[*font] {
+                    Font::TrueType(ttf) => self.renderer.load_texture_with(ttf.atlas_image().clone(), options),
+                    Font::Bitmap(_) => Err(TextureError::NotLoaded(id as u32)),
+                },
+                TextureSource::RenderTarget(target) => Ok(self.renderer.render_target_texture(*target)),
+                TextureSource::Unloaded => continue,
+            };
+
+            match texture {
+                Ok(texture) => self.textures[id] = Some(texture),
+                Err(e) => {
+                    console::error_1(&format!("Couldn't restore texture {}: {}", id, e).into());
+                    self.textures[id] = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load textures without keeping a copy of their image. If the GL context is lost, `reload` is called with each
+    /// texture ID to get its image back. Without one, images are kept in memory for that.
+    pub fn set_texture_reloader(&mut self, reload: impl FnMut(u32) -> Option<RgbaImage> + 'static) {
+        self.texture_reloader = Some(Box::new(reload));
+    }
+
+    /// Most time spent decoding assets each frame, in milliseconds. The budget is checked between assets, so at least
+    /// one is always decoded.
+    const ASSET_BUDGET: f64 = 4.0;
+
+    /// Decode assets that have finished downloading, as many as fit in the frame's budget. Images are decoded here on
+    /// the main thread and the `image` crate can't be interrupted, so a large one still takes a frame of its own.
+    /// Only one image is decoded a frame to keep that to a single long frame rather than several in a row.
+    fn process_assets(&mut self) {
+        for e in self.assets.receive() {
+            console::error_1(&format!("Couldn't reload asset: {}", e).into());
+        }
+
+        let started = js_sys::Date::now();
+        let mut decoded_image = false;
+        while let Some((handle, kind, bytes)) = self.assets.next_fetched() {
+            decoded_image = kind == AssetKind::Image;
+            match self.decode_asset(kind, bytes) {
+                Ok(asset @ Asset::Sound(_)) => self.assets.finish_later(handle, asset),
+                result => self.assets.finish(handle, result),
+            }
+            if decoded_image || js_sys::Date::now() - started > Self::ASSET_BUDGET {
+                break;
+            }
+        }
+
+        // Reloads share the budget, including the one image a frame.
+        while !decoded_image && js_sys::Date::now() - started <= Self::ASSET_BUDGET {
+            let (handle, asset, bytes) = match self.assets.next_reloaded() {
+                Some(reloaded) => reloaded,
+                None => break,
+            };
+            decoded_image = matches!(asset, Asset::Texture(_));
+            match self.reload_asset(&asset, bytes) {
+                Ok(Some(asset)) => self.assets.finish(handle, Ok(asset)),
+                Ok(None) => {}
+                Err(e) => {
+                    let path = self.assets.path(handle).unwrap_or_default();
+                    console::error_1(&format!("Couldn't reload {}: {}", path, e).into());
+                    continue;
+                }
+            }
+            self.events.push(Event::AssetReloaded { handle });
+        }
+
+        // Sounds are decoded by the browser in the background.
+        for (handle, asset) in self.assets.decoding() {
+            if let (Asset::Sound(sound), Some(audio)) = (&asset, &self.audio) {
+                if audio.is_loaded(*sound) {
+                    self.assets.finish(handle, Ok(asset));
+                } else if audio.has_failed(*sound) {
+                    self.assets.finish(handle, Err(LagomError::AssetDecode(String::from("the browser couldn't decode the sound"))));
+                }
+            }
+        }
+    }
+
+    fn decode_asset(&mut self, kind: AssetKind, bytes: Vec<u8>) -> Result<Asset, LagomError> {
+        match kind {
+            AssetKind::Image => {
+                let image = image::load_from_memory(&bytes)?.to_rgba8();
+                self.load_texture(image).map(Asset::Texture)
+            }
+            AssetKind::Sound => match &mut self.audio {
+                Some(audio) => audio.load_sound(&bytes).map(Asset::Sound),
+                None => Err(LagomError::Audio(String::from("no Web Audio in this browser"))),
+            },
+            AssetKind::Font { size } => self.load_ttf(bytes, size).map(Asset::Font),
+            AssetKind::Data => Ok(Asset::Data(bytes)),
+            AssetKind::Manifest => Err(LagomError::AssetDecode(String::from("manifests are read as they arrive"))),
+        }
+    }
+
+    /// Decode a new version of an asset into the same texture, sound or font. Returns the asset if it changed.
+    fn reload_asset(&mut self, asset: &Asset, bytes: Vec<u8>) -> Result<Option<Asset>, LagomError> {
+        match asset {
+            Asset::Texture(texture) => {
+                let image = image::load_from_memory(&bytes)?.to_rgba8();
+                self.replace_texture(*texture, image)?;
+            }
+            Asset::Sound(sound) => match &mut self.audio {
+                Some(audio) => audio.replace_sound(*sound, &bytes)?,
+                None => return Err(LagomError::Audio(String::from("no Web Audio in this browser"))),
+            },
+            Asset::Font(font) => self.replace_ttf(*font, bytes)?,
+            Asset::Data(_) => return Ok(Some(Asset::Data(bytes))),
+            Asset::Manifest(_) => {}
+        }
+        Ok(None)
+    }
+
+    pub fn assets(&self) -> &AssetServer {
+        &self.assets
     }
 
-    fn read_input() {}
+    /// Start loading assets by URL.
+    pub fn assets_mut(&mut self) -> &mut AssetServer {
+        &mut self.assets
+    }
+
+    /// Show the loading screen instead of running updates until `handle` has loaded, e.g. a manifest of everything
+    /// the game needs to start.
+    pub fn wait_for(&mut self, handle: Handle) {
+        self.loading = Some(handle);
+    }
+
+    /// Draw the loading screen with `loading` instead of the default progress bar. It's called each frame while
+    /// waiting, `assets().progress()` says how far along things are.
+    pub fn set_loading_screen(&mut self, loading: LoadingFn) {
+        self.loading_fn = loading;
+    }
+
+    /// Apply the input events that arrived since the last frame, before any updates run.
+    fn read_input(&mut self) {
+        let events = self.dom_input.drain();
+        self.input.process(events, self.renderer.screen());
+        let gamepad_events = self.input.poll_gamepads(&gamepad::read_gamepads());
+        self.events.extend(gamepad_events);
+    }
+
+    /// Keyboard, mouse, touch and gamepad state for this frame.
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// Named actions and axes, checked against this frame's input with `action_down` and friends.
+    pub fn actions(&self) -> &ActionMap {
+        &self.actions
+    }
+
+    /// Bind or rebind actions and axes.
+    pub fn actions_mut(&mut self) -> &mut ActionMap {
+        &mut self.actions
+    }
+
+    pub fn action_down(&self, action: &str) -> bool {
+        self.actions.is_down(&self.input, action)
+    }
+
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.actions.just_pressed(&self.input, action)
+    }
+
+    pub fn action_just_released(&self, action: &str) -> bool {
+        self.actions.just_released(&self.input, action)
+    }
+
+    /// -1 to 1.
+    pub fn action_axis(&self, axis: &str) -> f32 {
+        self.actions.axis(&self.input, axis)
+    }
+
+    pub fn audio(&self) -> Option<&Audio> {
+        self.audio.as_ref()
+    }
 
-    fn update(&mut self, delta: f64) {
-        (self.update_fn)(self, delta);
+    /// Load and play sounds, and set volumes. `None` if the browser can't play sound.
+    pub fn audio_mut(&mut self) -> Option<&mut Audio> {
+        self.audio.as_mut()
+    }
+
+    /// Shake gamepad `index` for `duration` seconds, where the browser supports it. `strong` and `weak` are the two
+    /// motors' strengths, 0 to 1.
+    pub fn rumble(&self, index: u32, strong: f32, weak: f32, duration: f64) {
+        gamepad::rumble(index, strong, weak, duration);
+    }
+
+    /// Where the mouse is in the world, through the game's camera.
+    pub fn mouse_world(&self) -> cgmath::Vector2<f32> {
+        self.input.mouse_world(&self.camera)
+    }
+
+    /// Put `scene` on top of the current one, which is paused until it's popped.
+    pub fn push_scene(&mut self, scene: Scene, transition: Transition) {
+        self.scenes.push(scene, transition);
+    }
+
+    /// Drop the top scene and go back to the one under it.
+    pub fn pop_scene(&mut self, transition: Transition) {
+        self.scenes.pop(transition);
+    }
+
+    /// Swap the top scene for `scene`, e.g. to go from the menu to the first level.
+    pub fn replace_scene(&mut self, scene: Scene, transition: Transition) {
+        self.scenes.replace(scene, transition);
+    }
+
+    /// Run `f` with the scene stack taken out of the game, so scenes can be handed the game. Scene changes made
+    /// meanwhile are kept.
+    fn with_scenes(&mut self, f: impl FnOnce(&mut SceneStack, &mut LagomGame)) {
+        let mut scenes = std::mem::take(&mut self.scenes);
+        f(&mut scenes, self);
+        scenes.append_queued(&mut self.scenes);
+        self.scenes = scenes;
+    }
+
+    fn advance_scenes(&mut self, delta: f64) {
+        self.with_scenes(|scenes, game| scenes.advance(delta, |hook, scene| scene.call(hook, game)));
+    }
+
+    fn fixed_update_scenes(&mut self, step: f64) {
+        self.with_scenes(|scenes, game| {
+            if let Some(scene) = scenes.top_mut() {
+                for system in &scene.fixed_systems {
+                    system(&mut scene.world, game, step);
+                }
+            }
+        });
+    }
+
+    fn update_scenes(&mut self, delta: f64) {
+        self.with_scenes(|scenes, game| {
+            if let Some(scene) = scenes.top_mut() {
+                for system in &scene.systems {
+                    system(&mut scene.world, game, delta);
+                }
+            }
+            for scene in scenes.visible_mut() {
+                scene.world.render_text(game);
+                for system in &scene.render_systems {
+                    system(&mut scene.world, game, delta);
+                }
+            }
+        });
+    }
+
+    fn submit(&mut self, command: &DrawCommand) {
+        match command {
+            DrawCommand::Texture(texture, params) => {
+                if let Some(Some(loaded)) = self.textures.get(*texture as usize) {
+                    self.renderer.draw_image(*texture, loaded, params)
+                }
+            }
+            DrawCommand::Shape(shape, params) => self.renderer.draw_shape(shape, params),
+        }
     }
 
     fn render_frame(&mut self) {
+        let mut draw_buffer = std::mem::take(&mut self.draw_buffer);
+
+        // Render targets go first so the scene can draw them. They aren't moved by the camera.
+        let mut targets: Vec<RenderTargetId> = draw_buffer.iter().filter_map(|req| req.0).collect();
+        targets.sort_unstable();
+        targets.dedup();
+
+        self.renderer.set_view(Matrix4::identity());
+        for target in targets {
+            self.renderer.begin_render_target(target);
+            for req in draw_buffer.iter().filter(|req| req.0 == Some(target)) {
+                self.submit(&req.1);
+            }
+            self.renderer.flush(&self.textures);
+        }
+
         self.renderer.clear();
+        self.renderer.set_view(self.camera.view_matrix());
+
+        for req in draw_buffer.iter().filter(|req| req.0.is_none()) {
+            self.submit(&req.1);
+        }
+        self.renderer.flush(&self.textures);
 
-        for req in &self.draw_buffer {
-            self.renderer.draw_image(&self.textures[req.0 as usize], req.1, req.2);
+        // Scene transitions cover the whole screen, wherever the camera is.
+        if let Some(colour) = self.scenes.fade_colour() {
+            let (width, height) = self.renderer.target_size();
+            let cover = Shape::Rect { width: width as f32, height: height as f32, fill: Fill::Solid };
+            self.renderer.set_view(Matrix4::identity());
+            self.renderer.draw_shape(&cover, &DrawParams { tint: colour, ..DrawParams::at(0.0, 0.0) });
+            self.renderer.flush(&self.textures);
         }
+        self.renderer.present(&self.textures);
+
+        // Hand the allocation back for next frame.
+        draw_buffer.clear();
+        self.draw_buffer = draw_buffer;
+        self.draw_target = None;
+    }
 
-        self.draw_buffer.clear();
+    pub fn draw(&mut self, texture: u32, params: DrawParams) {
+        self.draw_buffer.push((self.draw_target, DrawCommand::Texture(texture, params)));
     }
 
-    pub fn draw(&mut self, texture: u32, x: u32, y: u32) {
-        self.draw_buffer.push((texture, x, y));
+    /// Draw a line, rectangle, circle or polygon. `params.tint` is the colour.
+    pub fn draw_shape(&mut self, shape: Shape, params: DrawParams) {
+        self.draw_buffer.push((self.draw_target, DrawCommand::Shape(shape, params)));
+    }
+
+    /// Draw text with its top left at the params position. Rotation, scale and origin apply to the block as a whole
+    /// and `params.tint` is the text colour. Nothing is drawn for a font that isn't loaded.
+    pub fn draw_text(&mut self, font: FontId, text: &str, style: &TextStyle, params: DrawParams) {
+        if let Some(Font::TrueType(ttf)) = self.fonts.get_mut(font) {
+            if ttf.prepare(text) {
+                let id = ttf.texture() as usize;
+                if let Some(Some(texture)) = self.textures.get_mut(id) {
+                    if let Err(e) = self.renderer.update_texture(texture, ttf.atlas_image(), &self.texture_sources[id].1) {
+                        console::log_1(&format!("Couldn't update glyph atlas: {}", e).into());
+                    }
+                }
+            }
+        }
+
+        let glyphs = match self.fonts.get(font) {
+            Some(font) => layout(font, text, style),
+            None => return,
+        };
+
+        for glyph in glyphs {
+            let glyph_params = DrawParams {
+                source: Some(glyph.source),
+                origin: (params.origin.0 - glyph.x, params.origin.1 - glyph.y),
+                ..params.clone()
+            };
+            self.draw(glyph.texture, glyph_params);
+        }
     }
 
-    pub fn load_texture(&mut self, source: RgbaImage) -> u32 {
-        let tex = self.renderer.load_texture(source);
-        self.textures.push(tex);
-        return (self.textures.len() - 1) as u32;
+    /// Load an AngelCode BMFont from the text `.fnt` file and its page images, in page order.
+    pub fn load_bmfont(&mut self, fnt: &str, pages: Vec<RgbaImage>) -> Result<FontId, LagomError> {
+        let pages = pages.into_iter()
+            .map(|page| self.load_texture(page))
+            .collect::<Result<Vec<u32>, _>>()?;
+        self.fonts.push(Font::Bitmap(BitmapFont::from_bmfont(fnt, &pages)?));
+        Ok(self.fonts.len() - 1)
+    }
+
+    /// Load a fixed width font from an image of `glyph_width` x `glyph_height` cells holding `characters` in order.
+    pub fn load_monospace_font(&mut self, source: RgbaImage, glyph_width: u32, glyph_height: u32, characters: &str)
+                               -> Result<FontId, LagomError> {
+        let texture_width = source.width();
+        let texture = self.load_texture(source)?;
+        let font = BitmapFont::monospace(texture, texture_width, glyph_width, glyph_height, characters)
+            .inspect_err(|_| {
+                let _ = self.unload_texture(texture);
+            })?;
+        self.fonts.push(Font::Bitmap(font));
+        Ok(self.fonts.len() - 1)
+    }
+
+    /// Load a TrueType or OpenType font, e.g. from `include_bytes!`, to draw at `size` pixels high. Glyphs are
+    /// rasterized into an atlas texture as they are first drawn.
+    pub fn load_ttf(&mut self, bytes: Vec<u8>, size: f32) -> Result<FontId, LagomError> {
+        let font = TrueTypeFont::parse(bytes)?;
+        self.add_ttf(font, size)
+    }
+
+    /// Swap a TrueType font for a new version of it, keeping its ID and size.
+    pub fn replace_ttf(&mut self, font: FontId, bytes: Vec<u8>) -> Result<(), LagomError> {
+        let parsed = TrueTypeFont::parse(bytes)?;
+        let atlas = GlyphAtlas::new(256, self.renderer.max_texture_size().min(4096) as u32);
+        match self.fonts.get_mut(font) {
+            Some(Font::TrueType(ttf)) => {
+                ttf.replace_font(parsed, atlas);
+                let id = ttf.texture() as usize;
+                if let Some(Some(texture)) = self.textures.get_mut(id) {
+                    self.renderer.update_texture(texture, ttf.atlas_image(), &self.texture_sources[id].1)?;
+                }
+                Ok(())
+            }
+            Some(Font::Bitmap(_)) => Err(LagomError::AssetDecode(format!("Font {} isn't a TrueType font", font))),
+            None => Err(LagomError::UnknownFont(font)),
+        }
+    }
+
+    /// Another size of an already loaded TrueType font.
+    pub fn load_ttf_size(&mut self, font: FontId, size: f32) -> Result<FontId, LagomError> {
+        match self.fonts.get(font).ok_or(LagomError::UnknownFont(font))? {
+            Font::TrueType(ttf) => {
+                let font = ttf.font().clone();
+                self.add_ttf(font, size)
+            }
+            Font::Bitmap(_) => Err(LagomError::AssetDecode(format!("Font {} is a bitmap font, only TrueType fonts can be loaded at another size", font))),
+        }
+    }
+
+    fn add_ttf(&mut self, font: ab_glyph::FontArc, size: f32) -> Result<FontId, LagomError> {
+        // Never let the atlas outgrow what the device can upload.
+        let atlas = GlyphAtlas::new(256, self.renderer.max_texture_size().min(4096) as u32);
+        let options = TextureOptions { filter: TextureFilter::Linear, ..Default::default() };
+        let texture = self.renderer.load_texture_with(atlas.image().clone(), &options)?;
+        let texture = self.add_texture(texture, TextureSource::FontAtlas(self.fonts.len()), options);
+
+        self.fonts.push(Font::TrueType(TrueTypeFont::new(font, size, texture, atlas)));
+        Ok(self.fonts.len() - 1)
+    }
+
+    /// Create an offscreen render target, returning it and the texture ID to draw its contents with.
+    /// The contents are stored upside down, so draw the texture with `flip_y`.
+    pub fn create_render_target(&mut self, width: i32, height: i32) -> Result<(RenderTargetId, u32), LagomError> {
+        let (target, texture) = self.renderer.create_render_target(width, height)?;
+        Ok((target, self.add_texture(texture, TextureSource::RenderTarget(target), TextureOptions::default())))
+    }
+
+    /// Send the following draws to a render target instead of the scene, until set back to `None`.
+    /// Resets to the scene at the end of every frame.
+    pub fn set_draw_target(&mut self, target: Option<RenderTargetId>) {
+        self.draw_target = target;
+    }
+
+    /// Full screen effects applied to the scene, in order, before it is shown.
+    pub fn post_effects(&self) -> &[PostEffect] {
+        self.renderer.post_effects()
+    }
+
+    /// Replace the post processing chain. An effect that fails to build is logged and removed.
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) -> Result<(), LagomError> {
+        self.renderer.set_post_effects(effects)
+    }
+
+    /// Create a named render layer. Layers with a lower `order` are drawn first, no matter when they were drawn in
+    /// update. Sprites in a `y_sort` layer are ordered by their y position rather than their depth.
+    pub fn add_layer(&mut self, name: &str, order: i32, y_sort: bool) -> LayerId {
+        self.renderer.add_layer(name, order, y_sort)
+    }
+
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.renderer.layer_id(name)
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.renderer.layer_mut(id)
+    }
+
+    /// Compile a custom material, see `Renderer::create_material`. Sprites opt in with `DrawParams::material`.
+    pub fn create_material(&mut self, vertex_source: Option<&str>, fragment_source: &str) -> Result<MaterialId, LagomError> {
+        self.renderer.create_material(vertex_source, fragment_source)
+    }
+
+    pub fn clone_material(&mut self, id: MaterialId) -> Result<MaterialId, LagomError> {
+        self.renderer.clone_material(id)
+    }
+
+    pub fn material_mut(&mut self, id: MaterialId) -> Result<&mut Material, LagomError> {
+        self.renderer.material_mut(id)
+    }
+
+    pub fn camera(&mut self) -> &mut Camera2D {
+        &mut self.camera
+    }
+
+    /// Render at a fixed low resolution and scale up to the canvas in whole pixels. `None` renders at canvas size.
+    pub fn set_virtual_resolution(&mut self, resolution: Option<(i32, i32)>) -> Result<(), LagomError> {
+        self.renderer.set_virtual_resolution(resolution)?;
+        self.sync_camera_viewport();
+        Ok(())
+    }
+
+    pub fn load_texture(&mut self, source: RgbaImage) -> Result<u32, LagomError> {
+        self.load_texture_with(source, &TextureOptions::default())
+    }
+
+    pub fn load_texture_with(&mut self, source: RgbaImage, options: &TextureOptions) -> Result<u32, LagomError> {
+        let retained = match self.texture_reloader {
+            Some(_) => TextureSource::Reload,
+            None => TextureSource::Image(source.clone()),
+        };
+        let tex = self.renderer.load_texture_with(source, options)?;
+        Ok(self.add_texture(tex, retained, options.clone()))
+    }
+
+    fn add_texture(&mut self, texture: Texture, source: TextureSource, options: TextureOptions) -> u32 {
+        self.textures.push(Some(texture));
+        self.texture_sources.push((source, options));
+        (self.textures.len() - 1) as u32
+    }
+
+    /// Swap a texture's image for a new one, which can be a different size. Everything drawing with the ID picks up
+    /// the new image and the sampling options are kept.
+    pub fn replace_texture(&mut self, id: u32, source: RgbaImage) -> Result<(), LagomError> {
+        match self.textures.get_mut(id as usize) {
+            Some(Some(texture)) if self.renderer.is_render_target(texture) => Err(TextureError::RenderTarget(id).into()),
+            Some(Some(texture)) => {
+                self.renderer.update_texture(texture, &source, &self.texture_sources[id as usize].1)?;
+                if let (TextureSource::Image(image), _) = &mut self.texture_sources[id as usize] {
+                    *image = source;
+                }
+                Ok(())
+            }
+            _ => Err(TextureError::NotLoaded(id).into()),
+        }
+    }
+
+    /// Free a texture's GPU memory. Drawing with the ID afterwards draws nothing, and the ID isn't handed out again.
+    /// Render target textures belong to their target and can't be unloaded.
+    pub fn unload_texture(&mut self, id: u32) -> Result<(), LagomError> {
+        match self.textures.get_mut(id as usize) {
+            Some(Some(texture)) if self.renderer.is_render_target(texture) => Err(TextureError::RenderTarget(id).into()),
+            Some(slot @ Some(_)) => {
+                self.renderer.delete_texture(slot.take().expect("matched above"));
+                self.texture_sources[id as usize].0.unload();
+                Ok(())
+            }
+            _ => Err(TextureError::NotLoaded(id).into()),
+        }
+    }
+
+    pub fn texture_stats(&self) -> TextureStats {
+        let mut stats = TextureStats::default();
+        for texture in self.textures.iter().flatten() {
+            stats.add(texture);
+        }
+        stats
     }
 }
 
+#[cfg(test)]
+mod test {
+    use image::RgbaImage;
+
+    use crate::TextureSource;
+
+    #[test]
+    fn unloaded_textures_drop_their_image() {
+        let mut source = TextureSource::Image(RgbaImage::new(4, 2));
+        assert_eq!(source.unload().map(|image| image.dimensions()), Some((4, 2)));
+        assert!(matches!(source, TextureSource::Unloaded));
+        assert!(source.unload().is_none());
+    }
+}
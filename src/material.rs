@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlUniformLocation};
+
+use crate::error::LagomError;
+use crate::gl::Gl;
+use crate::renderer::{compile_shader, link_program};
+
+pub type MaterialId = usize;
+
+/// The material every sprite uses unless told otherwise.
+pub const DEFAULT_MATERIAL: MaterialId = 0;
+
+/// Fixed attribute slots, bound before linking so every program works with the renderer's vertex array.
+pub const POSITION_ATTRIBUTE: u32 = 0;
+pub const TEXCOORD_ATTRIBUTE: u32 = 1;
+pub const COLOR_ATTRIBUTE: u32 = 2;
+
+pub const SPRITE_VERTEX_SHADER: &str = r#"#version 300 es
+
+        in vec2 a_position;
+        in vec2 a_texcoord;
+        in vec4 a_color;
+
+        uniform mat4 u_matrix;
+
+        out vec2 v_texcoord;
+        out vec4 v_color;
+
+        void main() {
+           gl_Position = u_matrix * vec4(a_position, 0.0, 1.0);
+           v_texcoord = a_texcoord;
+           v_color = a_color;
+        }
+        "#;
+
+pub const SPRITE_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+        in vec4 v_color;
+
+        uniform sampler2D u_texture;
+
+        out vec4 outColor;
+
+        void main() {
+           outColor = texture(u_texture, v_texcoord) * v_color;
+        }
+        "#;
+
+/// Blends the sprite towards `u_flash_color` by `u_flash_amount`, keeping its alpha.
+pub const HIT_FLASH_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+        in vec4 v_color;
+
+        uniform sampler2D u_texture;
+        uniform vec4 u_flash_color;
+        uniform float u_flash_amount;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 color = texture(u_texture, v_texcoord) * v_color;
+           outColor = vec4(mix(color.rgb, u_flash_color.rgb, u_flash_amount), color.a);
+        }
+        "#;
+
+/// Looks each pixel's red channel up in row 0 of the `u_palette` texture.
+pub const PALETTE_SWAP_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+        in vec4 v_color;
+
+        uniform sampler2D u_texture;
+        uniform sampler2D u_palette;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 index = texture(u_texture, v_texcoord);
+           vec4 color = texture(u_palette, vec2(index.r, 0.5));
+           outColor = vec4(color.rgb, color.a * index.a) * v_color;
+        }
+        "#;
+
+/// Draws `u_outline_color` on transparent pixels next to opaque ones. `u_texel_size` is 1 / texture size.
+pub const OUTLINE_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+        in vec4 v_color;
+
+        uniform sampler2D u_texture;
+        uniform vec4 u_outline_color;
+        uniform vec2 u_texel_size;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 color = texture(u_texture, v_texcoord) * v_color;
+           float neighbours = texture(u_texture, v_texcoord + vec2(u_texel_size.x, 0.0)).a
+                            + texture(u_texture, v_texcoord - vec2(u_texel_size.x, 0.0)).a
+                            + texture(u_texture, v_texcoord + vec2(0.0, u_texel_size.y)).a
+                            + texture(u_texture, v_texcoord - vec2(0.0, u_texel_size.y)).a;
+           outColor = color.a > 0.0 ? color : u_outline_color * min(neighbours, 1.0);
+        }
+        "#;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    Mat4([f32; 16]),
+    /// A texture ID from `LagomGame::load_texture`, bound to its own texture unit.
+    Texture(u32),
+}
+
+impl UniformValue {
+    /// Whether this value can be written to a uniform of the given GL type.
+    fn matches(&self, gl_type: u32) -> bool {
+        match self {
+            UniformValue::Float(_) => gl_type == WebGl2RenderingContext::FLOAT,
+            UniformValue::Vec2(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC2,
+            UniformValue::Vec3(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC3,
+            UniformValue::Vec4(_) => gl_type == WebGl2RenderingContext::FLOAT_VEC4,
+            UniformValue::Int(_) => gl_type == WebGl2RenderingContext::INT || gl_type == WebGl2RenderingContext::BOOL,
+            UniformValue::Mat4(_) => gl_type == WebGl2RenderingContext::FLOAT_MAT4,
+            UniformValue::Texture(_) => gl_type == WebGl2RenderingContext::SAMPLER_2D,
+        }
+    }
+}
+
+struct UniformInfo {
+    location: WebGlUniformLocation,
+    gl_type: u32,
+}
+
+/// A linked shader program and the uniforms it exposes.
+pub struct ShaderProgram {
+    pub program: WebGlProgram,
+    uniforms: HashMap<String, UniformInfo>,
+    /// Kept to build the program again if the context is lost.
+    vertex_source: String,
+    fragment_source: String,
+}
+
+impl ShaderProgram {
+    pub fn new(gl: &Gl, vertex_source: &str, fragment_source: &str) -> Result<Self, LagomError> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, vertex_source)?;
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, fragment_source)
+            .inspect_err(|_| gl.delete_shader(Some(&vert_shader)))?;
+        let program = link_program(gl, &vert_shader, &frag_shader);
+
+        // The program keeps what it needs, the shader objects can go.
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+        let program = program?;
+
+        let mut uniforms = HashMap::new();
+        let count = gl.get_program_parameter(&program, WebGl2RenderingContext::ACTIVE_UNIFORMS).as_f64().unwrap_or(0.0);
+        for index in 0..count as u32 {
+            if let Some(info) = gl.get_active_uniform(&program, index) {
+                // Arrays are reported as `name[0]`, let them be set by their plain name.
+                let name = info.name().trim_end_matches("[0]").to_string();
+                if let Some(location) = gl.get_uniform_location(&program, &name) {
+                    uniforms.insert(name, UniformInfo { location, gl_type: info.type_() });
+                }
+            }
+        }
+
+        Ok(Self { program, uniforms, vertex_source: vertex_source.to_string(), fragment_source: fragment_source.to_string() })
+    }
+
+    /// Compile the same sources again, for a restored context.
+    pub fn recompile(&self, gl: &Gl) -> Result<Self, LagomError> {
+        Self::new(gl, &self.vertex_source, &self.fragment_source)
+    }
+
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
+    }
+
+    pub fn uniform_names(&self) -> impl Iterator<Item=&String> {
+        self.uniforms.keys()
+    }
+
+    /// Check that a uniform exists and will accept the value.
+    pub fn validate(&self, name: &str, value: &UniformValue) -> Result<(), LagomError> {
+        match self.uniforms.get(name) {
+            None => Err(LagomError::Uniform(format!("Shader has no active uniform named {}", name))),
+            Some(info) if !value.matches(info.gl_type) => Err(LagomError::Uniform(format!("Uniform {} can't be set to {:?}", name, value))),
+            Some(_) => Ok(())
+        }
+    }
+
+    /// Write a uniform on the currently bound program. `texture_unit` is used for `UniformValue::Texture`, binding the
+    /// texture itself is up to the caller. Unknown names are ignored.
+    pub fn set_uniform(&self, gl: &Gl, name: &str, value: &UniformValue, texture_unit: i32) {
+        let location = match self.uniforms.get(name) {
+            Some(info) => Some(&info.location),
+            None => return,
+        };
+
+        match value {
+            UniformValue::Float(v) => gl.uniform1f(location, *v),
+            UniformValue::Vec2(v) => gl.uniform2fv_with_f32_array(location, v),
+            UniformValue::Vec3(v) => gl.uniform3fv_with_f32_array(location, v),
+            UniformValue::Vec4(v) => gl.uniform4fv_with_f32_array(location, v),
+            UniformValue::Int(v) => gl.uniform1i(location, *v),
+            UniformValue::Mat4(v) => gl.uniform_matrix4fv_with_f32_array(location, false, v),
+            UniformValue::Texture(_) => gl.uniform1i(location, texture_unit),
+        }
+    }
+}
+
+/// A shader program plus the uniform values to draw with. Sprites using different materials are never batched
+/// together, so share materials between sprites where possible.
+pub struct Material {
+    pub shader: Rc<ShaderProgram>,
+    uniforms: Vec<(String, UniformValue)>,
+}
+
+impl Material {
+    pub fn new(shader: Rc<ShaderProgram>) -> Self {
+        Self { shader, uniforms: Vec::new() }
+    }
+
+    pub fn set(&mut self, name: &str, value: UniformValue) -> Result<(), LagomError> {
+        self.shader.validate(name, &value)?;
+
+        match self.uniforms.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&UniformValue> {
+        self.uniforms.iter().find(|(existing, _)| existing == name).map(|(_, value)| value)
+    }
+
+    pub fn uniforms(&self) -> &[(String, UniformValue)] {
+        &self.uniforms
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::LagomError;
+use crate::material::{MaterialId, UniformValue};
+
+/// A full screen pass applied to the finished scene, in the order they are listed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    /// Darkened scanlines with optional barrel distortion.
+    Crt { intensity: f32, curvature: f32 },
+    /// Bright parts of the image bleed light into their surroundings.
+    Bloom { threshold: f32, intensity: f32, radius: f32 },
+    /// `brightness` is added, `contrast` and `saturation` are 1.0 for no change, `tint` is multiplied.
+    ColorGrade { brightness: f32, contrast: f32, saturation: f32, tint: [f32; 3] },
+    /// Blend the whole screen towards `color`. 0 is no fade, 1 is a solid colour.
+    Fade { color: [f32; 3], amount: f32 },
+    /// A material created with `create_material`. It samples the scene from `u_texture`.
+    Custom(MaterialId),
+}
+
+impl PostEffect {
+    /// Check a custom effect's material is one of the first `material_count` created.
+    pub fn check_material(&self, material_count: usize) -> Result<(), LagomError> {
+        match self {
+            PostEffect::Custom(id) if *id >= material_count => Err(LagomError::UnknownMaterial(*id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fragment shader source for the built in effects.
+    pub fn shader(&self) -> Option<&'static str> {
+        match self {
+            PostEffect::Crt { .. } => Some(CRT_FRAGMENT_SHADER),
+            PostEffect::Bloom { .. } => Some(BLOOM_FRAGMENT_SHADER),
+            PostEffect::ColorGrade { .. } => Some(COLOR_GRADE_FRAGMENT_SHADER),
+            PostEffect::Fade { .. } => Some(FADE_FRAGMENT_SHADER),
+            PostEffect::Custom(_) => None,
+        }
+    }
+
+    /// Uniform values for this pass, given the size in pixels of the image being processed.
+    pub fn uniforms(&self, resolution: (i32, i32)) -> Vec<(&'static str, UniformValue)> {
+        let resolution = UniformValue::Vec2([resolution.0 as f32, resolution.1 as f32]);
+        match *self {
+            PostEffect::Crt { intensity, curvature } => vec![
+                ("u_resolution", resolution),
+                ("u_intensity", UniformValue::Float(intensity)),
+                ("u_curvature", UniformValue::Float(curvature)),
+            ],
+            PostEffect::Bloom { threshold, intensity, radius } => vec![
+                ("u_resolution", resolution),
+                ("u_threshold", UniformValue::Float(threshold)),
+                ("u_intensity", UniformValue::Float(intensity)),
+                ("u_radius", UniformValue::Float(radius)),
+            ],
+            PostEffect::ColorGrade { brightness, contrast, saturation, tint } => vec![
+                ("u_brightness", UniformValue::Float(brightness)),
+                ("u_contrast", UniformValue::Float(contrast)),
+                ("u_saturation", UniformValue::Float(saturation)),
+                ("u_tint", UniformValue::Vec3(tint)),
+            ],
+            PostEffect::Fade { color, amount } => vec![
+                ("u_color", UniformValue::Vec3(color)),
+                ("u_amount", UniformValue::Float(amount)),
+            ],
+            PostEffect::Custom(_) => Vec::new(),
+        }
+    }
+}
+
+const CRT_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+
+        uniform sampler2D u_texture;
+        uniform vec2 u_resolution;
+        uniform float u_intensity;
+        uniform float u_curvature;
+
+        out vec4 outColor;
+
+        void main() {
+           vec2 centred = v_texcoord * 2.0 - 1.0;
+           centred *= 1.0 + u_curvature * dot(centred.yx, centred.yx);
+           vec2 uv = centred * 0.5 + 0.5;
+
+           if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+              outColor = vec4(0.0, 0.0, 0.0, 1.0);
+              return;
+           }
+
+           vec4 color = texture(u_texture, uv);
+           float line = 0.5 + 0.5 * sin(uv.y * u_resolution.y * 3.14159265);
+           outColor = vec4(color.rgb * (1.0 - u_intensity * line), color.a);
+        }
+        "#;
+
+const BLOOM_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+
+        uniform sampler2D u_texture;
+        uniform vec2 u_resolution;
+        uniform float u_threshold;
+        uniform float u_intensity;
+        uniform float u_radius;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 base = texture(u_texture, v_texcoord);
+           vec2 texel = u_radius / u_resolution;
+
+           vec3 glow = vec3(0.0);
+           float total = 0.0;
+           for (int x = -3; x <= 3; x++) {
+              for (int y = -3; y <= 3; y++) {
+                 float weight = 1.0 / (1.0 + float(x * x + y * y));
+                 vec3 sample_color = texture(u_texture, v_texcoord + vec2(float(x), float(y)) * texel).rgb;
+                 glow += max(sample_color - u_threshold, 0.0) * weight;
+                 total += weight;
+              }
+           }
+
+           outColor = vec4(base.rgb + glow / total * u_intensity, base.a);
+        }
+        "#;
+
+const COLOR_GRADE_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+
+        uniform sampler2D u_texture;
+        uniform float u_brightness;
+        uniform float u_contrast;
+        uniform float u_saturation;
+        uniform vec3 u_tint;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 color = texture(u_texture, v_texcoord);
+           vec3 rgb = color.rgb + u_brightness;
+           rgb = (rgb - 0.5) * u_contrast + 0.5;
+           float luma = dot(rgb, vec3(0.299, 0.587, 0.114));
+           rgb = mix(vec3(luma), rgb, u_saturation) * u_tint;
+           outColor = vec4(clamp(rgb, 0.0, 1.0), color.a);
+        }
+        "#;
+
+const FADE_FRAGMENT_SHADER: &str = r#"#version 300 es
+
+        precision highp float;
+
+        in vec2 v_texcoord;
+
+        uniform sampler2D u_texture;
+        uniform vec3 u_color;
+        uniform float u_amount;
+
+        out vec4 outColor;
+
+        void main() {
+           vec4 color = texture(u_texture, v_texcoord);
+           outColor = vec4(mix(color.rgb, u_color, u_amount), mix(color.a, 1.0, u_amount));
+        }
+        "#;
+
+#[cfg(test)]
+mod test {
+    use crate::error::LagomError;
+    use crate::post::PostEffect;
+
+    #[test]
+    fn built_in_shaders_declare_their_uniforms() {
+        let effects = [
+            PostEffect::Crt { intensity: 0.5, curvature: 0.1 },
+            PostEffect::Bloom { threshold: 0.8, intensity: 1.0, radius: 2.0 },
+            PostEffect::ColorGrade { brightness: 0.0, contrast: 1.0, saturation: 1.0, tint: [1.0, 1.0, 1.0] },
+            PostEffect::Fade { color: [0.0, 0.0, 0.0], amount: 0.5 },
+        ];
+
+        for effect in effects.iter() {
+            let source = effect.shader().unwrap();
+            for (name, _) in effect.uniforms((320, 240)) {
+                assert!(source.contains(&format!(" {};", name)), "{:?} is missing {}", effect, name);
+            }
+        }
+    }
+
+    #[test]
+    fn custom_effects_need_an_existing_material() {
+        assert!(PostEffect::Custom(1).check_material(2).is_ok());
+        assert!(matches!(PostEffect::Custom(2).check_material(2), Err(LagomError::UnknownMaterial(2))));
+        assert!(PostEffect::Fade { color: [0.0, 0.0, 0.0], amount: 1.0 }.check_material(0).is_ok());
+    }
+}
@@ -1,29 +1,125 @@
-use cgmath::Matrix4;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use cgmath::{Matrix4, SquareMatrix};
 use image::RgbaImage;
 use wasm_bindgen::JsCast;
-use wasm_bindgen::prelude::*;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlTexture, WebGlUniformLocation, WebGlVertexArrayObject};
+use web_sys::{console, HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlShader, WebGlTexture, WebGlVertexArrayObject};
+
+use crate::gl::Gl;
+use crate::input::EventListener;
+
+use crate::error::{LagomError, parse_shader_log, ShaderStage};
+use crate::batch::{DrawParams, Layer, LayerId, SpriteBatch, VERTEX_SIZE};
+use crate::material::{COLOR_ATTRIBUTE, DEFAULT_MATERIAL, Material, MaterialId, POSITION_ATTRIBUTE, ShaderProgram,
+                      SPRITE_FRAGMENT_SHADER, SPRITE_VERTEX_SHADER, TEXCOORD_ATTRIBUTE, UniformValue};
+use crate::post::PostEffect;
+use crate::scaling::{ScalePolicy, Screen};
+use crate::shapes::Shape;
+
+pub type RenderTargetId = usize;
+
+/// Texture ID meaning "the offscreen image being copied", rather than one of the game's textures.
+const SOURCE_TEXTURE: u32 = u32::MAX;
+
+/// Texture ID for the renderer's own 1x1 white texture, used for shapes.
+const WHITE_TEXTURE: u32 = u32::MAX - 1;
 
 pub struct Renderer {
-    gl: WebGl2RenderingContext,
-    program: WebGlProgram,
-    tex_location: WebGlUniformLocation,
-    vertex_array: WebGlVertexArrayObject,
-    matrix_location: WebGlUniformLocation,
-    canvas_height: i32,
-    canvas_width: i32,
+    gl: Gl,
+    /// Index 0 is the default sprite material.
+    materials: Vec<Material>,
+    /// `None` on WebGL1 without vertex array objects.
+    vertex_array: Option<WebGlVertexArrayObject>,
+    vertex_buffer: WebGlBuffer,
+    white_texture: Texture,
+    batch: SpriteBatch,
+    view: Matrix4<f32>,
+    /// Offscreen target used when rendering at a fixed virtual resolution.
+    virtual_target: Option<RenderTarget>,
+    /// Offscreen targets created by the game.
+    render_targets: Vec<RenderTarget>,
+    /// The game's render target currently being drawn to, `None` while drawing the scene.
+    current_target: Option<RenderTargetId>,
+    /// Applied in order between drawing the scene and showing it on the canvas.
+    post_effects: Vec<PostEffect>,
+    /// Materials compiled for the built in effects, keyed by their shader source.
+    post_materials: Vec<(&'static str, MaterialId)>,
+    /// Canvas sized targets the post processing chain bounces between.
+    post_targets: Vec<RenderTarget>,
+    canvas: HtmlCanvasElement,
+    screen: Screen,
+    /// Set by the window's resize event, the canvas is measured again on the next `poll_resize`.
+    resize_pending: Rc<Cell<bool>>,
+    _resize_listener: Option<EventListener>,
+    /// Largest width or height the context accepts for a texture.
+    max_texture_size: i32,
+    /// Set by the canvas' context lost and restored events.
+    context_state: Rc<Cell<ContextState>>,
+    _context_listeners: [EventListener; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContextState {
+    Ready,
+    /// The browser has taken the context away. Every GL object is invalid until it comes back.
+    Lost,
+    /// The context is back, but still empty.
+    Restored,
+}
+
+/// The vertex array and buffer every sprite is drawn from, plus the 1x1 white texture used for shapes.
+fn create_gl_resources(gl: &Gl) -> Result<(Option<WebGlVertexArrayObject>, WebGlBuffer, Texture), LagomError> {
+    let vertex_array = gl.create_vertex_array();
+    if vertex_array.is_none() && !gl.is_webgl1() {
+        return Err(LagomError::GlResource("vertex array"));
+    }
+    gl.bind_vertex_array(vertex_array.as_ref());
+
+    // All sprites share one interleaved buffer that is refilled every frame.
+    let vertex_buffer = gl.create_buffer().ok_or(LagomError::GlResource("vertex buffer"))?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
+    set_up_attributes(gl);
+
+    gl.enable(WebGl2RenderingContext::BLEND);
+    gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+
+    let white_texture = gl.create_texture().ok_or(LagomError::GlResource("texture"))?;
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&white_texture));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D, 0, WebGl2RenderingContext::RGBA as i32, 1, 1, 0,
+        WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE, Some(&[255, 255, 255, 255]),
+    ).map_err(|e| TextureError::UploadFailed(format!("{:?}", e)))?;
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+                      WebGl2RenderingContext::NEAREST as i32);
+    let white_texture = Texture { tex: white_texture, width: 1, height: 1, mipmaps: false };
+
+    Ok((vertex_array, vertex_buffer, white_texture))
+}
+
+/// Point the attributes at the bound vertex buffer. Done once for a vertex array object, or before every draw
+/// without one.
+fn set_up_attributes(gl: &Gl) {
+    let stride = (VERTEX_SIZE * 4) as i32;
+    gl.enable_vertex_attrib_array(POSITION_ATTRIBUTE);
+    gl.vertex_attrib_pointer_with_i32(POSITION_ATTRIBUTE, 2, WebGl2RenderingContext::FLOAT, false, stride, 0);
+    gl.enable_vertex_attrib_array(TEXCOORD_ATTRIBUTE);
+    gl.vertex_attrib_pointer_with_i32(TEXCOORD_ATTRIBUTE, 2, WebGl2RenderingContext::FLOAT, false, stride, 8);
+    gl.enable_vertex_attrib_array(COLOR_ATTRIBUTE);
+    gl.vertex_attrib_pointer_with_i32(COLOR_ATTRIBUTE, 4, WebGl2RenderingContext::FLOAT, false, stride, 16);
 }
 
 
-fn compile_shader(
-    context: &WebGl2RenderingContext,
+pub fn compile_shader(
+    context: &Gl,
     shader_type: u32,
     source: &str,
-) -> Result<WebGlShader, String> {
+) -> Result<WebGlShader, LagomError> {
+    let stage = if shader_type == WebGl2RenderingContext::VERTEX_SHADER { ShaderStage::Vertex } else { ShaderStage::Fragment };
     let shader = context
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-    context.shader_source(&shader, source);
+        .ok_or(LagomError::GlResource("shader"))?;
+    context.shader_source(&shader, source, stage);
     context.compile_shader(&shader);
 
     if context
@@ -33,22 +129,29 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(context
+        let log = context
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        context.delete_shader(Some(&shader));
+        Err(LagomError::ShaderCompile { stage, errors: parse_shader_log(&log, source), log })
     }
 }
 
-fn link_program(
-    context: &WebGl2RenderingContext,
+pub fn link_program(
+    context: &Gl,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
+) -> Result<WebGlProgram, LagomError> {
     let program = context
-        .create_program().ok_or_else(|| String::from("Unable to create shader object"))?;
+        .create_program().ok_or(LagomError::GlResource("shader program"))?;
 
     context.attach_shader(&program, vert_shader);
     context.attach_shader(&program, frag_shader);
+
+    // Pin the attributes so one vertex array layout works for every program.
+    context.bind_attrib_location(&program, POSITION_ATTRIBUTE, "a_position");
+    context.bind_attrib_location(&program, TEXCOORD_ATTRIBUTE, "a_texcoord");
+    context.bind_attrib_location(&program, COLOR_ATTRIBUTE, "a_color");
     context.link_program(&program);
 
     if context
@@ -58,79 +161,463 @@ fn link_program(
     {
         Ok(program)
     } else {
-        Err(context
+        let log = context
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            .unwrap_or_else(|| String::from("Unknown error creating program object"));
+        context.delete_program(Some(&program));
+        Err(LagomError::ShaderLink(log))
     }
 }
 
 
 impl Renderer {
-    pub fn new(canvas_id: &str) -> Result<Self, JsValue> {
-        let document = web_sys::window().unwrap().document().unwrap();
-        let canvas = document.get_element_by_id(canvas_id).unwrap();
-        let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
+    pub fn new(canvas_id: &str) -> Result<Self, LagomError> {
+        let canvas = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id(canvas_id))
+            .and_then(|element| element.dyn_into::<HtmlCanvasElement>().ok())
+            .ok_or_else(|| LagomError::CanvasMissing(canvas_id.to_string()))?;
+
+
+        let gl = Gl::from_canvas(&canvas)?;
+        if gl.is_webgl1() {
+            console::log_1(&"WebGL2 isn't available, falling back to WebGL1".into());
+        }
+
+        // WebGL 2 guarantees at least 2048.
+        let max_texture_size = gl.get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|size| size.as_f64())
+            .unwrap_or(2048.0) as i32;
+
+        let sprite_shader = ShaderProgram::new(&gl, SPRITE_VERTEX_SHADER, SPRITE_FRAGMENT_SHADER)?;
+        let materials = vec![Material::new(Rc::new(sprite_shader))];
+
+        let (vertex_array, vertex_buffer, white_texture) = create_gl_resources(&gl)?;
+
+        // Let the browser restore the context after losing it, and note when it does so everything can be rebuilt.
+        let context_state = Rc::new(Cell::new(ContextState::Ready));
+        let lost_state = context_state.clone();
+        let on_lost = EventListener::new(canvas.as_ref(), "webglcontextlost", move |event| {
+            event.prevent_default();
+            lost_state.set(ContextState::Lost);
+        });
+        let restored_state = context_state.clone();
+        let on_restored = EventListener::new(canvas.as_ref(), "webglcontextrestored", move |_| {
+            restored_state.set(ContextState::Restored);
+        });
+
+        // Zooming changes the pixel ratio and fires this too.
+        let resize_pending = Rc::new(Cell::new(true));
+        let pending = resize_pending.clone();
+        // Without it the canvas just keeps its first size.
+        let on_resize = web_sys::window()
+            .map(|window| EventListener::new(window.as_ref(), "resize", move |_| pending.set(true)));
+        let screen = Screen::new(ScalePolicy::Stretch, canvas.width() as i32, canvas.height() as i32);
+
+        Ok(Self {
+            gl,
+            materials,
+            vertex_array,
+            vertex_buffer,
+            white_texture,
+            batch: SpriteBatch::default(),
+            view: Matrix4::identity(),
+            virtual_target: None,
+            render_targets: Vec::new(),
+            current_target: None,
+            post_effects: Vec::new(),
+            post_materials: Vec::new(),
+            post_targets: Vec::new(),
+            canvas,
+            screen,
+            resize_pending,
+            _resize_listener: on_resize,
+            max_texture_size,
+            context_state,
+            _context_listeners: [on_lost, on_restored],
+        })
+    }
+
+    /// Whether the GL context is usable. Nothing should be drawn while it is `Lost`, and `restore` has to be called
+    /// once it is `Restored`.
+    pub fn context_state(&self) -> ContextState {
+        self.context_state.get()
+    }
+
+    /// Rebuild everything the renderer owns after the context comes back: shader programs, the vertex array and
+    /// buffer, and render targets, which come back empty. The game's own textures have to be uploaded again separately.
+    pub fn restore(&mut self) -> Result<(), LagomError> {
+        let (vertex_array, vertex_buffer, white_texture) = create_gl_resources(&self.gl)?;
+        self.vertex_array = vertex_array;
+        self.vertex_buffer = vertex_buffer;
+        self.white_texture = white_texture;
+
+        // Materials can share a program, keep it that way.
+        let mut programs: Vec<(*const ShaderProgram, Rc<ShaderProgram>)> = Vec::new();
+        for material in &mut self.materials {
+            let old = Rc::as_ptr(&material.shader);
+            let program = match programs.iter().find(|(ptr, _)| *ptr == old) {
+                Some((_, program)) => program.clone(),
+                None => {
+                    let program = Rc::new(material.shader.recompile(&self.gl)?);
+                    programs.push((old, program.clone()));
+                    program
+                }
+            };
+            material.shader = program;
+        }
+
+        if let Some(target) = &self.virtual_target {
+            let (width, height) = (target.texture.width, target.texture.height);
+            self.virtual_target = Some(self.create_target(width, height)?);
+        }
+        for id in 0..self.render_targets.len() {
+            let (width, height) = (self.render_targets[id].texture.width, self.render_targets[id].texture.height);
+            self.render_targets[id] = self.create_target(width, height)?;
+        }
+        // Remade at the right size when next needed.
+        self.post_targets.clear();
+
+        self.context_state.set(ContextState::Ready);
+        Ok(())
+    }
+
+    /// The texture behind a render target, to draw it with.
+    pub fn render_target_texture(&self, id: RenderTargetId) -> Texture {
+        self.render_targets[id].texture.clone()
+    }
+
+    pub fn canvas(&self) -> &HtmlCanvasElement {
+        &self.canvas
+    }
+
+    pub fn canvas_size(&self) -> (i32, i32) {
+        (self.screen.width, self.screen.height)
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn set_scale_policy(&mut self, policy: ScalePolicy) {
+        self.screen.policy = policy;
+        self.resize_pending.set(true);
+    }
+
+    /// Measure the canvas if the window has been resized since last time, resizing its backing store to match.
+    /// Returns true if the screen changed.
+    pub fn poll_resize(&mut self) -> bool {
+        if !self.resize_pending.replace(false) {
+            return false;
+        }
+
+        let pixel_ratio = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+        let (mut css_width, mut css_height) = (self.canvas.client_width() as f64, self.canvas.client_height() as f64);
+        if css_width <= 0.0 || css_height <= 0.0 {
+            // Not laid out, e.g. hidden. Assume it's shown at its attribute size.
+            css_width = self.canvas.width() as f64 / pixel_ratio;
+            css_height = self.canvas.height() as f64 / pixel_ratio;
+        }
+
+        let before = self.logical_and_ratio();
+        if !self.screen.resize(css_width, css_height, pixel_ratio) {
+            return false;
+        }
+
+        if self.canvas.width() as i32 != self.screen.width || self.canvas.height() as i32 != self.screen.height {
+            self.canvas.set_width(self.screen.width as u32);
+            self.canvas.set_height(self.screen.height as u32);
+        }
+        self.logical_and_ratio() != before
+    }
+
+    fn logical_and_ratio(&self) -> ((i32, i32), f64) {
+        (self.screen.logical_size(), self.screen.pixel_ratio)
+    }
+
+    /// Set the world to screen transform applied to everything drawn from now on, usually `Camera2D::view_matrix`.
+    pub fn set_view(&mut self, view: Matrix4<f32>) {
+        self.view = view;
+    }
+
+    /// Render everything at `width` x `height` and scale it up to the canvas by the largest whole number that fits.
+    /// Pass `None` to render straight to the canvas again.
+    pub fn set_virtual_resolution(&mut self, resolution: Option<(i32, i32)>) -> Result<(), LagomError> {
+        self.virtual_target = match resolution {
+            Some((width, height)) => Some(self.create_target(width, height)?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// The size of whatever is currently being drawn to: a render target, the virtual resolution or the screen, in
+    /// game pixels.
+    pub fn target_size(&self) -> (i32, i32) {
+        if let Some(id) = self.current_target {
+            let texture = &self.render_targets[id].texture;
+            return (texture.width, texture.height);
+        }
+
+        match &self.virtual_target {
+            Some(target) => (target.texture.width, target.texture.height),
+            None => self.screen.logical_size(),
+        }
+    }
+
+    /// Where in the bound framebuffer `target_size` is drawn to. Only the screen might not be covered completely.
+    fn target_viewport(&self) -> (i32, i32, i32, i32) {
+        if self.current_target.is_none() && self.virtual_target.is_none() {
+            return self.screen.viewport();
+        }
+        let (width, height) = self.target_size();
+        (0, 0, width, height)
+    }
+
+    fn create_target(&self, width: i32, height: i32) -> Result<RenderTarget, LagomError> {
+        check_target_size(width, height, self.max_texture_size)?;
+
+        let texture: WebGlTexture = self.gl.create_texture().ok_or(LagomError::GlResource("texture"))?;
+        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+
+        let rgba = WebGl2RenderingContext::RGBA;
+        self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D, 0, rgba as i32,
+            width, height, 0, rgba, WebGl2RenderingContext::UNSIGNED_BYTE, None,
+        ).map_err(|e| TextureError::UploadFailed(format!("{:?}", e)))?;
+
+        let mut texture = Texture { tex: texture, width, height, mipmaps: false };
+        self.apply_texture_options(&mut texture, &TextureOptions::default());
+
+        let framebuffer = self.gl.create_framebuffer().ok_or(LagomError::GlResource("framebuffer"))?;
+        self.gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        self.gl.framebuffer_texture_2d(WebGl2RenderingContext::FRAMEBUFFER, WebGl2RenderingContext::COLOR_ATTACHMENT0,
+                                       WebGl2RenderingContext::TEXTURE_2D, Some(&texture.tex), 0);
+        self.gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+        Ok(RenderTarget { framebuffer, texture })
+    }
+
+    /// Create an offscreen target. The returned texture shows whatever was last drawn into it and can be drawn
+    /// like any other, keep in mind it is stored upside down so draw it with `flip_y`.
+    pub fn create_render_target(&mut self, width: i32, height: i32) -> Result<(RenderTargetId, Texture), LagomError> {
+        let target = self.create_target(width, height)?;
+        let texture = target.texture.clone();
+        self.render_targets.push(target);
+        Ok((self.render_targets.len() - 1, texture))
+    }
+
+    /// Whether the texture is a render target's, which lives as long as the target.
+    pub fn is_render_target(&self, texture: &Texture) -> bool {
+        self.render_targets.iter().any(|target| target.texture.tex == texture.tex)
+    }
 
-        let canvas_width = canvas.width();
-        let canvas_height = canvas.height();
+    /// Clear a render target and send everything drawn until the next `begin_render_target` or `clear` to it.
+    pub fn begin_render_target(&mut self, id: RenderTargetId) {
+        self.current_target = Some(id);
+        self.bind_target(Some(&self.render_targets[id].framebuffer), self.target_viewport(), [0.0, 0.0, 0.0, 0.0]);
+    }
+
+    /// Bind and clear a framebuffer, then restrict drawing to the `(x, y, width, height)` viewport.
+    fn bind_target(&self, framebuffer: Option<&WebGlFramebuffer>, (x, y, width, height): (i32, i32, i32, i32), color: [f32; 4]) {
+        self.gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer);
+        self.gl.clear_color(color[0], color[1], color[2], color[3]);
+        self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+        self.gl.viewport(x, y, width, height);
+    }
+
+    /// Canvas sized targets for post processing to bounce between. Returns false if they couldn't be made, in which
+    /// case post processing is skipped.
+    fn ensure_post_targets(&mut self) -> bool {
+        let size = (self.screen.width, self.screen.height);
+        let matches = |target: &RenderTarget| (target.texture.width, target.texture.height) == size;
+        if self.post_targets.len() != 2 || !self.post_targets.iter().all(matches) {
+            self.post_targets.clear();
+            match (self.create_target(size.0, size.1), self.create_target(size.0, size.1)) {
+                (Ok(first), Ok(second)) => self.post_targets = vec![first, second],
+                (Err(e), _) | (_, Err(e)) => {
+                    console::log_1(&format!("Post processing disabled: {}", e).into());
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Start drawing the scene. It goes to the canvas unless a virtual resolution or post processing is in use.
+    pub fn clear(&mut self) {
+        self.current_target = None;
+
+        let framebuffer = if let Some(target) = &self.virtual_target {
+            Some(target.framebuffer.clone())
+        } else if !self.post_effects.is_empty() && self.ensure_post_targets() {
+            Some(self.post_targets[0].framebuffer.clone())
+        } else {
+            None
+        };
+
+        self.bind_target(framebuffer.as_ref(), self.target_viewport(), [0.0, 0.0, 0.0, 0.0]);
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect] {
+        &self.post_effects
+    }
+
+    /// Replace the post processing chain. Fails without changing anything if a custom effect's material doesn't exist.
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) -> Result<(), LagomError> {
+        for effect in &effects {
+            effect.check_material(self.materials.len())?;
+        }
+        self.post_effects = effects;
+        Ok(())
+    }
+
+    /// Get the material for a post effect, compiling the built in ones on first use.
+    fn post_material(&mut self, effect: &PostEffect) -> Result<MaterialId, LagomError> {
+        if let PostEffect::Custom(id) = effect {
+            return Ok(*id);
+        }
+        let source = effect.shader().expect("built in effects all have a shader");
 
-        let gl: WebGl2RenderingContext = canvas
-            .get_context("webgl2")?
-            .unwrap()
-            .dyn_into::<WebGl2RenderingContext>()?;
+        let id = match self.post_materials.iter().find(|(existing, _)| *existing == source) {
+            Some((_, id)) => *id,
+            None => {
+                let id = self.create_material(None, source)?;
+                self.post_materials.push((source, id));
+                id
+            }
+        };
 
-        let vert_shader = compile_shader(
-            &gl,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            r#"#version 300 es
+        let resolution = (self.screen.width, self.screen.height);
+        for (name, value) in effect.uniforms(resolution) {
+            self.materials[id].set(name, value)?;
+        }
+        Ok(id)
+    }
 
-        in vec4 a_position;
-        in vec2 a_texcoord;
+    /// Draw `source` over the whole of the currently bound canvas sized target.
+    fn draw_fullscreen(&mut self, source: &Texture, material: MaterialId, textures: &[Option<Texture>]) {
+        let scale = (self.screen.width as f32 / source.width as f32, self.screen.height as f32 / source.height as f32);
 
-        uniform mat4 u_matrix;
+        // Framebuffer textures are stored bottom row first.
+        let params = DrawParams { scale, flip_y: true, material, ..Default::default() };
+        self.batch.push_sprite(SOURCE_TEXTURE, source.width as f32, source.height as f32, &params);
+        self.flush_to(textures, Some(source), (self.screen.width, self.screen.height));
+    }
 
-        out vec2 v_texcoord;
+    /// Put the finished scene on the canvas: scale up the virtual resolution target if there is one, then run the
+    /// post processing chain.
+    pub fn present(&mut self, textures: &[Option<Texture>]) {
+        self.current_target = None;
 
-        void main() {
-           gl_Position = u_matrix * a_position;
-           v_texcoord = a_texcoord;
+        let mut effects = Vec::new();
+        let post_targets_ready = !self.post_effects.is_empty() && self.ensure_post_targets();
+        for effect in self.post_effects.clone().into_iter().filter(|_| post_targets_ready) {
+            match self.post_material(&effect) {
+                Ok(material) => effects.push(material),
+                Err(e) => {
+                    // Drop it rather than compiling it and logging again every frame.
+                    console::log_1(&format!("Removing post effect {:?}: {}", effect, e).into());
+                    self.post_effects.retain(|existing| *existing != effect);
+                }
+            }
         }
-        "#)?;
 
-        let frag_shader = compile_shader(
-            &gl,
-            WebGl2RenderingContext::FRAGMENT_SHADER,
-            r#"#version 300 es
+        // Where the finished scene currently lives, if it isn't already on the canvas.
+        let mut source = match &self.virtual_target {
+            Some(target) => target.texture.clone(),
+            None if post_targets_ready => self.post_targets[0].texture.clone(),
+            None => return,
+        };
+        let mut next = 1;
 
-        precision highp float;
+        if effects.is_empty() && self.virtual_target.is_none() {
+            // Every effect failed, but the scene is still sitting in a post target. Copy it out as is.
+            effects.push(DEFAULT_MATERIAL);
+        }
 
-        in vec2 v_texcoord;
+        let view = std::mem::replace(&mut self.view, Matrix4::identity());
+        let canvas_size = (self.screen.width, self.screen.height);
+        let canvas_viewport = (0, 0, canvas_size.0, canvas_size.1);
 
-        uniform sampler2D u_texture;
+        if self.virtual_target.is_some() {
+            let framebuffer = if effects.is_empty() {
+                None
+            } else {
+                Some(self.post_targets[0].framebuffer.clone())
+            };
+            self.bind_target(framebuffer.as_ref(), canvas_viewport, [0.0, 0.0, 0.0, 1.0]);
 
-        out vec4 outColor;
+            let (scale, x, y) = letterbox((source.width, source.height), canvas_size);
+            let params = DrawParams { scale: (scale as f32, scale as f32), flip_y: true, ..DrawParams::at(x as f32, y as f32) };
+            self.batch.push_sprite(SOURCE_TEXTURE, source.width as f32, source.height as f32, &params);
+            self.flush_to(textures, Some(&source), canvas_size);
 
-        void main() {
-           outColor = texture(u_texture, v_texcoord);
+            if !effects.is_empty() {
+                source = self.post_targets[0].texture.clone();
+            }
         }
-        "#)?;
 
-        let program = link_program(&gl, &vert_shader, &frag_shader)?;
-        gl.use_program(Some(&program));
+        for (i, material) in effects.iter().enumerate() {
+            let framebuffer = if i == effects.len() - 1 {
+                None
+            } else {
+                Some(self.post_targets[next].framebuffer.clone())
+            };
+            self.bind_target(framebuffer.as_ref(), canvas_viewport, [0.0, 0.0, 0.0, 1.0]);
+            self.draw_fullscreen(&source, *material, textures);
+
+            source = self.post_targets[next].texture.clone();
+            next = 1 - next;
+        }
+
+        self.view = view;
+    }
+
+    /// Create a named layer. Layers with a lower `order` are drawn first, `y_sort` layers order sprites by y.
+    pub fn add_layer(&mut self, name: &str, order: i32, y_sort: bool) -> LayerId {
+        self.batch.add_layer(name, order, y_sort)
+    }
+
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.batch.layer_id(name)
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.batch.layer_mut(id)
+    }
+
+    /// Queue a texture to be drawn. Nothing reaches the screen until `flush` is called.
+    pub fn draw_image(&mut self, texture_id: u32, texture: &Texture, params: &DrawParams) {
+        self.batch.push_sprite(texture_id, texture.width as f32, texture.height as f32, params);
+    }
+
+    /// Queue a shape to be drawn in `params.tint`.
+    pub fn draw_shape(&mut self, shape: &Shape, params: &DrawParams) {
+        self.batch.push_triangles(WHITE_TEXTURE, shape.triangles(), params);
+    }
 
-        // look up position.
-        let pos_attribute = gl.get_attrib_location(&program, "a_position") as u32;
-        let tex_attribute = gl.get_attrib_location(&program, "a_texcoord") as u32;
+    /// Sort everything queued since the last flush and draw it, one draw call per texture change.
+    pub fn flush(&mut self, textures: &[Option<Texture>]) {
+        self.flush_to(textures, None, self.target_size());
+    }
 
-        // Uniforms
-        let matrix_location = gl.get_uniform_location(&program, "u_matrix").expect("no matrix");
-        let tex_location = gl.get_uniform_location(&program, "u_texture").expect("no texture");
+    /// `source` is what `SOURCE_TEXTURE` refers to.
+    fn flush_to(&mut self, textures: &[Option<Texture>], source: Option<&Texture>, (width, height): (i32, i32)) {
+        if self.batch.is_empty() {
+            return;
+        }
+        self.batch.build();
 
-        let vertex_array = gl.create_vertex_array().expect("broken");
-        gl.bind_vertex_array(Some(&vertex_array));
+        self.gl.bind_vertex_array(self.vertex_array.as_ref());
 
-        let position_buffer = gl.create_buffer().expect("create buffer failed");
-        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&position_buffer));
+        let projection: Matrix4<f32> = cgmath::ortho(0_f32, width as f32, height as f32, 0_f32, -1_f32, 1_f32);
+        let matrix = UniformValue::Mat4(*(projection * self.view).as_ref());
+
+        self.gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.vertex_buffer));
+        if self.vertex_array.is_none() {
+            set_up_attributes(&self.gl);
+        }
 
         // Note that `Float32Array::view` is somewhat dangerous (hence the
         // `unsafe`!). This is creating a raw view into our module's
@@ -140,92 +627,174 @@ impl Renderer {
         //
         // As a result, after `Float32Array::view` we have to be very careful not to
         // do any memory allocations before it's dropped.
-        let positions: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
-        let pos_array = unsafe { js_sys::Float32Array::view(&positions) };
-
-        gl.buffer_data_with_array_buffer_view(WebGl2RenderingContext::ARRAY_BUFFER, &pos_array, WebGl2RenderingContext::STATIC_DRAW);
-        gl.enable_vertex_attrib_array(pos_attribute);
-        gl.vertex_attrib_pointer_with_i32(pos_attribute, 2, WebGl2RenderingContext::FLOAT, false, 0, 0);
+        unsafe {
+            let vertices = js_sys::Float32Array::view(&self.batch.vertices);
+            self.gl.buffer_data_with_array_buffer_view(WebGl2RenderingContext::ARRAY_BUFFER, &vertices,
+                                                       WebGl2RenderingContext::DYNAMIC_DRAW);
+        }
 
-        let tex_coord_buffer = gl.create_buffer().expect("create buffer failed.");
-        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&tex_coord_buffer));
-        let tex_coords: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
-        let tex_array = unsafe { js_sys::Float32Array::view(&tex_coords) };
-        gl.buffer_data_with_array_buffer_view(WebGl2RenderingContext::ARRAY_BUFFER, &tex_array, WebGl2RenderingContext::STATIC_DRAW);
-        gl.enable_vertex_attrib_array(tex_attribute);
-        gl.vertex_attrib_pointer_with_i32(tex_attribute, 2, WebGl2RenderingContext::FLOAT, true, 0, 0);
+        let mut current_material = None;
+        for batch in &self.batch.batches {
+            if current_material != Some(batch.material) {
+                // A material ID that was never created draws like a plain sprite.
+                let material = self.materials.get(batch.material).unwrap_or(&self.materials[DEFAULT_MATERIAL]);
+                self.use_material(material, &matrix, textures);
+                current_material = Some(batch.material);
+            }
 
-        Ok(Self {
-            gl,
-            program,
-            tex_location,
-            vertex_array,
-            matrix_location,
-            canvas_width: canvas_width as i32,
-            canvas_height: canvas_height as i32,
-        })
-    }
+            let texture = match (batch.texture, source) {
+                (SOURCE_TEXTURE, Some(source)) => source,
+                (WHITE_TEXTURE, _) => &self.white_texture,
+                (id, _) => match textures.get(id as usize) {
+                    Some(Some(texture)) => texture,
+                    // Unloaded, nothing to draw.
+                    _ => continue,
+                },
+            };
+            self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.tex));
+            self.gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, batch.first, batch.count);
+        }
 
-    pub fn clear(&self) {
-        self.gl.viewport(0, 0, self.canvas_width, self.canvas_height);
-        self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
-        self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+        self.batch.clear();
     }
 
-    pub fn draw_image(&self, texture: &Texture, x: u32, y: u32) {
+    /// Bind a material's program and upload its uniforms. Leaves texture unit 0 active for the sprite texture.
+    fn use_material(&self, material: &Material, matrix: &UniformValue, textures: &[Option<Texture>]) {
+        let shader = &material.shader;
+        self.gl.use_program(Some(&shader.program));
+        shader.set_uniform(&self.gl, "u_matrix", matrix, 0);
+        shader.set_uniform(&self.gl, "u_texture", &UniformValue::Int(0), 0);
 
-        // Do I need these?
-        self.gl.use_program(Some(&self.program));
-        self.gl.bind_vertex_array(Some(&self.vertex_array));
-
-        let texture_unit: i32 = 0;
-        self.gl.uniform1i(Some(&self.tex_location), texture_unit);
-
-        self.gl.active_texture(WebGl2RenderingContext::TEXTURE0 + texture_unit as u32);
-        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.tex));
+        // Unit 0 is the sprite texture, extra textures take the units after it.
+        let mut texture_unit = 1;
+        for (name, value) in material.uniforms() {
+            if let UniformValue::Texture(id) = value {
+                let texture = textures.get(*id as usize).and_then(|texture| texture.as_ref());
+                self.gl.active_texture(WebGl2RenderingContext::TEXTURE0 + texture_unit as u32);
+                self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture.map(|texture| &texture.tex));
+                shader.set_uniform(&self.gl, name, value, texture_unit);
+                texture_unit += 1;
+            } else {
+                shader.set_uniform(&self.gl, name, value, 0);
+            }
+        }
 
-        let matrix: cgmath::Matrix4<f32> = cgmath::ortho(0_f32, self.canvas_width as f32, self.canvas_height as f32,
-                                                         0_f32, -1_f32, 1_f32);
+        self.gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+    }
 
-        let translation = Matrix4::from_translation(cgmath::vec3(x as f32, y as f32, 1.0));
-        let scale = Matrix4::from_nonuniform_scale(texture.width as f32, texture.height as f32, 1.0);
-        let matrix: Matrix4<f32> = matrix * translation * scale;
+    /// Compile a material from GLSL ES 3.0 source. Leave out the vertex shader to use the default sprite one, which
+    /// passes `v_texcoord` and `v_color` through and samples from `u_texture`.
+    /// On WebGL1 the source is translated to GLSL ES 1.0, see `gl::glsl_100` for what that handles.
+    pub fn create_material(&mut self, vertex_source: Option<&str>, fragment_source: &str) -> Result<MaterialId, LagomError> {
+        let shader = ShaderProgram::new(&self.gl, vertex_source.unwrap_or(SPRITE_VERTEX_SHADER), fragment_source)?;
+        self.materials.push(Material::new(Rc::new(shader)));
+        Ok(self.materials.len() - 1)
+    }
 
-        // this clone is not ideal, but I don't know what else I can do.
-        let arr = cgmath::conv::array4(matrix).iter().flatten().cloned().collect::<Vec<f32>>();
+    /// A new material sharing the program of an existing one, so it can have its own uniform values.
+    pub fn clone_material(&mut self, id: MaterialId) -> Result<MaterialId, LagomError> {
+        let shader = self.material_mut(id)?.shader.clone();
+        self.materials.push(Material::new(shader));
+        Ok(self.materials.len() - 1)
+    }
 
-        self.gl.uniform_matrix4fv_with_f32_array(Some(&self.matrix_location), false, &arr);
-        self.gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
+    pub fn material_mut(&mut self, id: MaterialId) -> Result<&mut Material, LagomError> {
+        self.materials.get_mut(id).ok_or(LagomError::UnknownMaterial(id))
     }
 
     // TODO how can I write a test? https://devjournal.akigi.com/february-2020/2020-02-16.html#the-webgl-renderer
-    pub fn read_pixels(&self) -> Vec<u8> {
-        let mut dest = vec![0u8; (self.canvas_width * self.canvas_height * 4) as usize];
-        self.gl.read_pixels_with_opt_u8_array(0, 0, self.canvas_width, self.canvas_height,
+    pub fn read_pixels(&self) -> Result<Vec<u8>, LagomError> {
+        let mut dest = vec![0u8; (self.screen.width * self.screen.height * 4) as usize];
+        self.gl.read_pixels_with_opt_u8_array(0, 0, self.screen.width, self.screen.height,
                                               WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE,
-                                              Some(&mut dest));
-        return dest;
+                                              Some(&mut dest))
+            .map_err(|e| LagomError::ReadPixels(format!("{:?}", e)))?;
+        Ok(dest)
     }
 
-    pub fn load_texture(&self, source: RgbaImage) -> Texture {
-        let tx_width = source.width() as i32;
-        let tx_height = source.width() as i32;
+    pub fn load_texture(&self, source: RgbaImage) -> Result<Texture, TextureError> {
+        self.load_texture_with(source, &TextureOptions::default())
+    }
 
-        let pixels = source.into_raw();
+    pub fn load_texture_with(&self, source: RgbaImage, options: &TextureOptions) -> Result<Texture, TextureError> {
+        let (width, height, pixels) = upload_pixels(&source, self.max_texture_size)?;
 
-        let texture: WebGlTexture = self.gl.create_texture().unwrap();
-        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        let texture: WebGlTexture = self.gl.create_texture().ok_or(TextureError::CreateFailed)?;
+        let mut texture = Texture { tex: texture, width, height, mipmaps: options.mipmaps };
 
-        let rgba = WebGl2RenderingContext::RGBA;
+        if let Err(e) = self.upload(&texture.tex, (width, height), pixels) {
+            self.gl.delete_texture(Some(&texture.tex));
+            return Err(e);
+        }
+        self.apply_texture_options(&mut texture, options);
 
+        Ok(texture)
+    }
+
+    /// Replace the contents of a texture, which may change its size. `options` are what it was loaded with, they're
+    /// applied again as what WebGL1 supports depends on the size.
+    pub fn update_texture(&self, texture: &mut Texture, source: &RgbaImage, options: &TextureOptions)
+                          -> Result<(), TextureError> {
+        let (width, height, pixels) = upload_pixels(source, self.max_texture_size)?;
+        self.upload(&texture.tex, (width, height), pixels)?;
+        // Only once the GL storage really is the new size.
+        texture.width = width;
+        texture.height = height;
+        self.apply_texture_options(texture, options);
+        Ok(())
+    }
+
+    /// Bind the texture and copy pixels from `upload_pixels` into it.
+    fn upload(&self, texture: &WebGlTexture, (width, height): (i32, i32), pixels: &[u8]) -> Result<(), TextureError> {
+        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+
+        let rgba = WebGl2RenderingContext::RGBA;
         self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
             WebGl2RenderingContext::TEXTURE_2D, 0, rgba as i32,
-            tx_width, tx_height, 0, rgba, WebGl2RenderingContext::UNSIGNED_BYTE, Some(&pixels.as_slice()),
-        );
+            width, height, 0, rgba, WebGl2RenderingContext::UNSIGNED_BYTE, Some(pixels),
+        ).map_err(|e| TextureError::UploadFailed(format!("{:?}", e)))
+    }
+
+    pub fn max_texture_size(&self) -> i32 {
+        self.max_texture_size
+    }
+
+    pub fn set_texture_options(&self, texture: &mut Texture, options: &TextureOptions) {
+        self.gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.tex));
+        self.apply_texture_options(texture, options);
+    }
+
+    /// Free the GPU memory behind a texture.
+    pub fn delete_texture(&self, texture: Texture) {
+        self.gl.delete_texture(Some(&texture.tex));
+    }
+
+    /// Applies to the currently bound texture, which has to be `texture`.
+    fn apply_texture_options(&self, texture: &mut Texture, options: &TextureOptions) {
+        let target = WebGl2RenderingContext::TEXTURE_2D;
+        let options = &supported_options(options, (texture.width, texture.height), self.gl.is_webgl1());
+        texture.mipmaps = options.mipmaps;
 
-        self.gl.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
+        if options.mipmaps {
+            self.gl.generate_mipmap(target);
+        }
+
+        let (min_filter, mag_filter) = match (options.filter, options.mipmaps) {
+            (TextureFilter::Nearest, false) => (WebGl2RenderingContext::NEAREST, WebGl2RenderingContext::NEAREST),
+            (TextureFilter::Nearest, true) => (WebGl2RenderingContext::NEAREST_MIPMAP_NEAREST, WebGl2RenderingContext::NEAREST),
+            (TextureFilter::Linear, false) => (WebGl2RenderingContext::LINEAR, WebGl2RenderingContext::LINEAR),
+            (TextureFilter::Linear, true) => (WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR, WebGl2RenderingContext::LINEAR),
+        };
+        self.gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, min_filter as i32);
+        self.gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, mag_filter as i32);
 
-        Texture { tex: texture, width: tx_width, height: tx_height }
+        let wrap = match options.wrap {
+            TextureWrap::ClampToEdge => WebGl2RenderingContext::CLAMP_TO_EDGE,
+            TextureWrap::Repeat => WebGl2RenderingContext::REPEAT,
+            TextureWrap::MirroredRepeat => WebGl2RenderingContext::MIRRORED_REPEAT,
+        };
+        self.gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_S, wrap as i32);
+        self.gl.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_WRAP_T, wrap as i32);
     }
 
     // // This is async! The texture will not be available immediately.
@@ -284,8 +853,241 @@ impl Renderer {
     //     Ok(texture)
 }
 
+#[derive(Clone)]
 pub struct Texture {
     tex: WebGlTexture,
     width: i32,
     height: i32,
-}
\ No newline at end of file
+    mipmaps: bool,
+}
+
+impl Texture {
+    /// Roughly how much GPU memory the texture takes up.
+    pub fn bytes(&self) -> usize {
+        texture_bytes(self.width, self.height, self.mipmaps)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureError {
+    /// The image has no pixels.
+    Empty,
+    /// Bigger than the context's `MAX_TEXTURE_SIZE` in either direction.
+    TooLarge { width: u32, height: u32, max: i32 },
+    /// The pixel buffer doesn't hold width x height RGBA pixels.
+    WrongBufferSize { expected: usize, actual: usize },
+    CreateFailed,
+    UploadFailed(String),
+    /// No texture is loaded with this ID.
+    NotLoaded(u32),
+    /// The texture belongs to a render target, which has to manage it.
+    RenderTarget(u32),
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Empty => write!(f, "Texture image is empty"),
+            TextureError::TooLarge { width, height, max } =>
+                write!(f, "Texture is {}x{}, this device only supports up to {}x{}", width, height, max, max),
+            TextureError::WrongBufferSize { expected, actual } =>
+                write!(f, "Texture pixel buffer is {} bytes, expected {}", actual, expected),
+            TextureError::CreateFailed => write!(f, "Couldn't create a texture"),
+            TextureError::UploadFailed(e) => write!(f, "Texture upload failed: {}", e),
+            TextureError::NotLoaded(id) => write!(f, "No texture loaded with ID {}", id),
+            TextureError::RenderTarget(id) => write!(f, "Texture {} belongs to a render target", id),
+        }
+    }
+}
+
+/// Check an image can be uploaded as is, returning its size.
+fn check_texture_size(source: &RgbaImage, max: i32) -> Result<(i32, i32), TextureError> {
+    let (width, height) = source.dimensions();
+    if width == 0 || height == 0 {
+        return Err(TextureError::Empty);
+    }
+    if width as i64 > max as i64 || height as i64 > max as i64 {
+        return Err(TextureError::TooLarge { width, height, max });
+    }
+
+    let expected = width as usize * height as usize * 4;
+    if source.as_raw().len() < expected {
+        return Err(TextureError::WrongBufferSize { expected, actual: source.as_raw().len() });
+    }
+    Ok((width as i32, height as i32))
+}
+
+/// Check a render target of `width` x `height` can be created.
+fn check_target_size(width: i32, height: i32, max: i32) -> Result<(), TextureError> {
+    if width <= 0 || height <= 0 {
+        return Err(TextureError::Empty);
+    }
+    if width > max || height > max {
+        return Err(TextureError::TooLarge { width: width as u32, height: height as u32, max });
+    }
+    Ok(())
+}
+
+/// What to hand `tex_image_2d` for an image: its size and exactly width x height RGBA pixels, top row first. Rows are
+/// packed tightly, and being 4 bytes a pixel they always meet the default unpack alignment whatever the width.
+fn upload_pixels(source: &RgbaImage, max: i32) -> Result<(i32, i32, &[u8]), TextureError> {
+    let (width, height) = check_texture_size(source, max)?;
+    Ok((width, height, &source.as_raw()[..width as usize * height as usize * 4]))
+}
+
+/// Loaded texture count and approximate GPU memory use, from `LagomGame::texture_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextureStats {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl TextureStats {
+    pub fn add(&mut self, texture: &Texture) {
+        self.count += 1;
+        self.bytes += texture.bytes();
+    }
+}
+
+/// RGBA, 4 bytes a pixel. A full mip chain adds about a third on top.
+fn texture_bytes(width: i32, height: i32, mipmaps: bool) -> usize {
+    let base = width.max(0) as usize * height.max(0) as usize * 4;
+    if mipmaps { base + base / 3 } else { base }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureWrap {
+    ClampToEdge,
+    Repeat,
+    MirroredRepeat,
+}
+
+/// Sampling settings for a texture. The default is crisp pixel art: nearest filtering, clamped, no mipmaps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureOptions {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    pub mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self { filter: TextureFilter::Nearest, wrap: TextureWrap::ClampToEdge, mipmaps: false }
+    }
+}
+
+struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    texture: Texture,
+}
+
+/// WebGL1 can only repeat or mipmap textures whose sides are powers of two, anything else is clamped without mips.
+fn supported_options(options: &TextureOptions, (width, height): (i32, i32), webgl1: bool) -> TextureOptions {
+    let power_of_two = |size: i32| size > 0 && size & (size - 1) == 0;
+    if webgl1 && !(power_of_two(width) && power_of_two(height)) {
+        TextureOptions { wrap: TextureWrap::ClampToEdge, mipmaps: false, ..options.clone() }
+    } else {
+        options.clone()
+    }
+}
+
+/// Largest whole number scale that fits `source` inside `dest`, and the offset that centres it.
+/// Never scales below 1, if the destination is too small the result is cropped instead.
+fn letterbox((source_width, source_height): (i32, i32), (dest_width, dest_height): (i32, i32)) -> (i32, i32, i32) {
+    let scale = (dest_width / source_width).min(dest_height / source_height).max(1);
+    let x = (dest_width - source_width * scale) / 2;
+    let y = (dest_height - source_height * scale) / 2;
+    (scale, x, y)
+}
+
+#[cfg(test)]
+mod test {
+    use image::RgbaImage;
+
+    use crate::renderer::{check_target_size, check_texture_size, letterbox, supported_options, texture_bytes, TextureError,
+                          TextureOptions, TextureWrap, upload_pixels};
+
+    #[test]
+    fn letterbox_exact_fit() {
+        assert_eq!(letterbox((320, 180), (1280, 720)), (4, 0, 0));
+    }
+
+    #[test]
+    fn letterbox_centres_leftover_space() {
+        assert_eq!(letterbox((320, 180), (1366, 768)), (4, 43, 24));
+        assert_eq!(letterbox((160, 144), (800, 600)), (4, 80, 12));
+    }
+
+    #[test]
+    fn letterbox_never_below_one() {
+        assert_eq!(letterbox((320, 240), (200, 100)), (1, -60, -70));
+    }
+
+    #[test]
+    fn texture_bytes_counts_rgba_and_mips() {
+        assert_eq!(texture_bytes(16, 8, false), 512);
+        assert_eq!(texture_bytes(16, 8, true), 512 + 170);
+    }
+
+    #[test]
+    fn non_square_textures_keep_their_size() {
+        assert_eq!(check_texture_size(&RgbaImage::new(64, 16), 2048), Ok((64, 16)));
+        assert_eq!(check_texture_size(&RgbaImage::new(16, 64), 2048), Ok((16, 64)));
+    }
+
+    #[test]
+    fn odd_sized_textures_are_accepted() {
+        assert_eq!(check_texture_size(&RgbaImage::new(3, 7), 2048), Ok((3, 7)));
+        assert_eq!(check_texture_size(&RgbaImage::new(1, 1), 2048), Ok((1, 1)));
+    }
+
+    #[test]
+    fn uploads_are_tightly_packed_rows() {
+        for &(width, height) in [(64, 16), (16, 64), (3, 7), (1, 1)].iter() {
+            let image = RgbaImage::from_fn(width, height, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+            let (upload_width, upload_height, pixels) = upload_pixels(&image, 2048).unwrap();
+            assert_eq!((upload_width, upload_height), (width as i32, height as i32));
+            assert_eq!(pixels.len(), (width * height * 4) as usize);
+
+            // Row `y` starts at `y * width * 4`, so GL reads every pixel from the right place.
+            for (x, y) in [(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)].iter() {
+                let offset = ((y * width + x) * 4) as usize;
+                assert_eq!(&pixels[offset..offset + 4], &[*x as u8, *y as u8, 7, 255], "{}x{}", width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn textures_over_the_limit_are_rejected() {
+        assert_eq!(check_texture_size(&RgbaImage::new(4096, 16), 2048),
+                   Err(TextureError::TooLarge { width: 4096, height: 16, max: 2048 }));
+        assert_eq!(check_texture_size(&RgbaImage::new(2048, 2048), 2048), Ok((2048, 2048)));
+    }
+
+    #[test]
+    fn empty_textures_are_rejected() {
+        assert_eq!(check_texture_size(&RgbaImage::new(0, 8), 2048), Err(TextureError::Empty));
+    }
+
+    #[test]
+    fn render_target_sizes_are_checked() {
+        assert_eq!(check_target_size(320, 180, 2048), Ok(()));
+        assert_eq!(check_target_size(0, 180, 2048), Err(TextureError::Empty));
+        assert_eq!(check_target_size(320, -1, 2048), Err(TextureError::Empty));
+        assert_eq!(check_target_size(4096, 180, 2048), Err(TextureError::TooLarge { width: 4096, height: 180, max: 2048 }));
+    }
+
+    #[test]
+    fn webgl1_clamps_non_power_of_two_textures() {
+        let options = TextureOptions { wrap: TextureWrap::Repeat, mipmaps: true, ..Default::default() };
+        assert_eq!(supported_options(&options, (64, 32), true), options);
+        assert_eq!(supported_options(&options, (48, 32), false), options);
+        assert_eq!(supported_options(&options, (48, 32), true), TextureOptions::default());
+    }
+}
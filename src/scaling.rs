@@ -0,0 +1,122 @@
+/// How the game's drawing area follows the canvas as it changes size on the page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScalePolicy {
+    /// Fill the canvas. The game is as big as the canvas is in CSS pixels, drawn at the full device resolution.
+    Stretch,
+    /// Always `width` x `height` game pixels, scaled to fit the canvas keeping the aspect ratio. The leftover space
+    /// is left black.
+    Fit { width: i32, height: i32 },
+    /// Leave the canvas' backing store alone, the game is as big as its `width` and `height` attributes.
+    Fixed,
+}
+
+/// The canvas as the renderer sees it. `width` and `height` are the backing store size in device pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Screen {
+    pub policy: ScalePolicy,
+    pub width: i32,
+    pub height: i32,
+    /// Size on the page in CSS pixels.
+    pub css_width: f64,
+    pub css_height: f64,
+    /// Device pixels per CSS pixel.
+    pub pixel_ratio: f64,
+}
+
+impl Screen {
+    pub fn new(policy: ScalePolicy, width: i32, height: i32) -> Self {
+        Self { policy, width, height, css_width: width as f64, css_height: height as f64, pixel_ratio: 1.0 }
+    }
+
+    /// Take a new page size and pixel ratio, working out the backing store size for the policy. Returns true if
+    /// anything changed.
+    pub fn resize(&mut self, css_width: f64, css_height: f64, pixel_ratio: f64) -> bool {
+        let before = self.clone();
+
+        self.css_width = css_width;
+        self.css_height = css_height;
+        self.pixel_ratio = pixel_ratio;
+        if self.policy != ScalePolicy::Fixed {
+            self.width = ((css_width * pixel_ratio).round() as i32).max(1);
+            self.height = ((css_height * pixel_ratio).round() as i32).max(1);
+        }
+
+        *self != before
+    }
+
+    /// The size of the screen in game pixels, what the scene's projection covers.
+    pub fn logical_size(&self) -> (i32, i32) {
+        match self.policy {
+            ScalePolicy::Stretch => (self.css_width.round() as i32, self.css_height.round() as i32),
+            ScalePolicy::Fit { width, height } => (width, height),
+            ScalePolicy::Fixed => (self.width, self.height),
+        }
+    }
+
+    /// The part of the backing store the scene is drawn into, as x, y, width, height.
+    pub fn viewport(&self) -> (i32, i32, i32, i32) {
+        match self.policy {
+            ScalePolicy::Fit { width, height } => {
+                let scale = (self.width as f64 / width as f64).min(self.height as f64 / height as f64);
+                let (view_width, view_height) = ((width as f64 * scale).round() as i32, (height as f64 * scale).round() as i32);
+                ((self.width - view_width) / 2, (self.height - view_height) / 2, view_width, view_height)
+            }
+            _ => (0, 0, self.width, self.height),
+        }
+    }
+
+    /// Convert a position on the canvas in CSS pixels, e.g. from a mouse event, to game pixels.
+    pub fn css_to_logical(&self, x: f64, y: f64) -> (f32, f32) {
+        let (view_x, view_y, view_width, view_height) = self.viewport();
+        let (logical_width, logical_height) = self.logical_size();
+
+        // Into device pixels, then relative to the viewport.
+        let device_x = x * self.width as f64 / self.css_width.max(1.0) - view_x as f64;
+        let device_y = y * self.height as f64 / self.css_height.max(1.0) - view_y as f64;
+        ((device_x * logical_width as f64 / view_width.max(1) as f64) as f32,
+         (device_y * logical_height as f64 / view_height.max(1) as f64) as f32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scaling::{ScalePolicy, Screen};
+
+    #[test]
+    fn stretch_uses_device_pixels() {
+        let mut screen = Screen::new(ScalePolicy::Stretch, 300, 150);
+        assert!(screen.resize(640.0, 480.0, 2.0));
+        assert_eq!((screen.width, screen.height), (1280, 960));
+        assert_eq!(screen.logical_size(), (640, 480));
+        assert_eq!(screen.viewport(), (0, 0, 1280, 960));
+        assert!(!screen.resize(640.0, 480.0, 2.0));
+    }
+
+    #[test]
+    fn fit_letterboxes() {
+        let mut screen = Screen::new(ScalePolicy::Fit { width: 320, height: 180 }, 300, 150);
+        screen.resize(800.0, 600.0, 1.0);
+        assert_eq!(screen.logical_size(), (320, 180));
+        assert_eq!(screen.viewport(), (0, 75, 800, 450));
+    }
+
+    #[test]
+    fn fixed_keeps_the_backing_store() {
+        let mut screen = Screen::new(ScalePolicy::Fixed, 300, 150);
+        screen.resize(600.0, 300.0, 1.5);
+        assert_eq!((screen.width, screen.height), (300, 150));
+        assert_eq!(screen.logical_size(), (300, 150));
+    }
+
+    #[test]
+    fn css_positions_map_to_game_pixels() {
+        let mut screen = Screen::new(ScalePolicy::Fit { width: 320, height: 180 }, 300, 150);
+        screen.resize(800.0, 600.0, 2.0);
+        assert_eq!(screen.css_to_logical(400.0, 300.0), (160.0, 90.0));
+        assert_eq!(screen.css_to_logical(0.0, 75.0), (0.0, 0.0));
+
+        let mut screen = Screen::new(ScalePolicy::Stretch, 300, 150);
+        screen.resize(640.0, 480.0, 2.0);
+        assert_eq!(screen.css_to_logical(10.0, 20.0), (10.0, 20.0));
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+
+use crate::ecs::World;
+use crate::LagomGame;
+
+/// Runs on a scene's world, with the time since the last call in seconds.
+pub type System = fn(&mut World, &mut LagomGame, delta: f64);
+
+/// Called on a scene's world when the stack changes around it.
+pub type SceneHook = fn(&mut World, &mut LagomGame);
+
+/// A level, menu or overlay with its own entities and systems. Only the scene on top of the stack updates, but the
+/// ones under an overlay still draw.
+#[derive(Default)]
+pub struct Scene {
+    pub world: World,
+    /// Run every frame while the scene is on top.
+    pub systems: Vec<System>,
+    /// Run every fixed update while the scene is on top.
+    pub fixed_systems: Vec<System>,
+    /// Run every frame while the scene can be seen, after the top scene's `systems`, bottom scene first. Entities with
+    /// text components are drawn just before these.
+    pub render_systems: Vec<System>,
+    /// Keep drawing the scenes underneath, e.g. for a pause menu over the game.
+    pub overlay: bool,
+    /// The scene was pushed, or replaced another.
+    pub on_enter: Option<SceneHook>,
+    /// The scene was popped or replaced. It's dropped afterwards.
+    pub on_exit: Option<SceneHook>,
+    /// Another scene was pushed on top of this one.
+    pub on_pause: Option<SceneHook>,
+    /// The scene on top of this one was popped.
+    pub on_resume: Option<SceneHook>,
+}
+
+impl Scene {
+    fn hook(&self, hook: Hook) -> Option<SceneHook> {
+        match hook {
+            Hook::Enter => self.on_enter,
+            Hook::Exit => self.on_exit,
+            Hook::Pause => self.on_pause,
+            Hook::Resume => self.on_resume,
+        }
+    }
+
+    /// Call the scene's callback for `hook`, if it has one.
+    pub fn call(&mut self, hook: Hook, game: &mut LagomGame) {
+        if let Some(hook) = self.hook(hook) {
+            hook(&mut self.world, game);
+        }
+    }
+}
+
+/// How to get from one scene to the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    /// Switch straight away.
+    Cut,
+    /// Fade to `colour` over `duration` seconds, switch, then fade back in over the same time.
+    Fade { duration: f64, colour: [f32; 4] },
+}
+
+impl Transition {
+    /// Fade through black.
+    pub fn fade(duration: f64) -> Self {
+        Transition::Fade { duration, colour: [0.0, 0.0, 0.0, 1.0] }
+    }
+}
+
+/// Which callback a scene is owed after the stack changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hook {
+    Enter,
+    Exit,
+    Pause,
+    Resume,
+}
+
+enum SceneChange<S> {
+    Push(S),
+    Pop,
+    Replace(S),
+}
+
+/// A fade in progress. The change happens halfway through, when the screen is fully covered.
+struct Fade<S> {
+    duration: f64,
+    colour: [f32; 4],
+    time: f64,
+    change: Option<SceneChange<S>>,
+}
+
+/// Scenes with the current one on top. Changes are queued and made between frames, one transition at a time.
+pub struct SceneStack<S = Scene> {
+    scenes: Vec<S>,
+    queued: VecDeque<(SceneChange<S>, Transition)>,
+    fade: Option<Fade<S>>,
+}
+
+impl<S> Default for SceneStack<S> {
+    fn default() -> Self {
+        Self { scenes: Vec::new(), queued: VecDeque::new(), fade: None }
+    }
+}
+
+impl<S> SceneStack<S> {
+    /// Put `scene` on top, pausing the current one.
+    pub fn push(&mut self, scene: S, transition: Transition) {
+        self.queued.push_back((SceneChange::Push(scene), transition));
+    }
+
+    /// Remove the top scene, resuming the one under it.
+    pub fn pop(&mut self, transition: Transition) {
+        self.queued.push_back((SceneChange::Pop, transition));
+    }
+
+    /// Swap the top scene for `scene`, or push it if there's none.
+    pub fn replace(&mut self, scene: S, transition: Transition) {
+        self.queued.push_back((SceneChange::Replace(scene), transition));
+    }
+
+    /// Take the changes queued on `other`, e.g. by scene callbacks while this stack was borrowed.
+    pub fn append_queued(&mut self, other: &mut SceneStack<S>) {
+        self.queued.append(&mut other.queued);
+    }
+
+    pub fn top(&self) -> Option<&S> {
+        self.scenes.last()
+    }
+
+    pub fn top_mut(&mut self) -> Option<&mut S> {
+        self.scenes.last_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /// Whether a transition is running or changes are waiting.
+    pub fn is_changing(&self) -> bool {
+        self.fade.is_some() || !self.queued.is_empty()
+    }
+
+    /// The colour to cover the screen with for the current fade, with its alpha scaled by how far through it is.
+    pub fn fade_colour(&self) -> Option<[f32; 4]> {
+        let fade = self.fade.as_ref()?;
+        let opacity = (1.0 - (fade.time - fade.duration).abs() / fade.duration).clamp(0.0, 1.0) as f32;
+        let [r, g, b, a] = fade.colour;
+        Some([r, g, b, a * opacity])
+    }
+
+    /// Move the current fade along by `delta` seconds and make any changes that are due, calling `call` with each
+    /// scene that's owed a callback. Changes queued after a fade wait for it to finish.
+    pub fn advance(&mut self, delta: f64, mut call: impl FnMut(Hook, &mut S)) {
+        if let Some(fade) = &mut self.fade {
+            fade.time += delta;
+            let finished = fade.time >= fade.duration * 2.0;
+            let change = if fade.time >= fade.duration { fade.change.take() } else { None };
+            if finished {
+                self.fade = None;
+            }
+            if let Some(change) = change {
+                self.apply(change, &mut call);
+            }
+        }
+
+        while self.fade.is_none() {
+            match self.queued.pop_front() {
+                None => break,
+                Some((change, Transition::Fade { duration, colour })) if duration > 0.0 => {
+                    self.fade = Some(Fade { duration, colour, time: 0.0, change: Some(change) });
+                }
+                Some((change, _)) => self.apply(change, &mut call),
+            }
+        }
+    }
+
+    fn apply(&mut self, change: SceneChange<S>, call: &mut impl FnMut(Hook, &mut S)) {
+        match change {
+            SceneChange::Push(scene) => {
+                if let Some(top) = self.scenes.last_mut() {
+                    call(Hook::Pause, top);
+                }
+                self.enter(scene, call);
+            }
+            SceneChange::Pop => {
+                if let Some(mut top) = self.scenes.pop() {
+                    call(Hook::Exit, &mut top);
+                    if let Some(below) = self.scenes.last_mut() {
+                        call(Hook::Resume, below);
+                    }
+                }
+            }
+            SceneChange::Replace(scene) => {
+                if let Some(mut top) = self.scenes.pop() {
+                    call(Hook::Exit, &mut top);
+                }
+                self.enter(scene, call);
+            }
+        }
+    }
+
+    fn enter(&mut self, scene: S, call: &mut impl FnMut(Hook, &mut S)) {
+        self.scenes.push(scene);
+        if let Some(top) = self.scenes.last_mut() {
+            call(Hook::Enter, top);
+        }
+    }
+}
+
+impl SceneStack<Scene> {
+    /// The scenes to draw, bottom first: the top one and any under it showing through overlays.
+    pub fn visible_mut(&mut self) -> &mut [Scene] {
+        let bottom = self.scenes.iter().rposition(|scene| !scene.overlay).unwrap_or(0);
+        &mut self.scenes[bottom..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{Hook, Scene, SceneStack, Transition};
+
+    fn advance(stack: &mut SceneStack<&'static str>, delta: f64) -> Vec<(Hook, &'static str)> {
+        let mut calls = Vec::new();
+        stack.advance(delta, |hook, scene| calls.push((hook, *scene)));
+        calls
+    }
+
+    #[test]
+    fn push_pop_replace_call_hooks_in_order() {
+        let mut stack = SceneStack::default();
+        stack.push("menu", Transition::Cut);
+        assert!(stack.is_empty());
+        assert_eq!(advance(&mut stack, 0.0), vec![(Hook::Enter, "menu")]);
+
+        stack.replace("level", Transition::Cut);
+        stack.push("pause", Transition::Cut);
+        assert_eq!(advance(&mut stack, 0.0), vec![
+            (Hook::Exit, "menu"),
+            (Hook::Enter, "level"),
+            (Hook::Pause, "level"),
+            (Hook::Enter, "pause"),
+        ]);
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack.top(), Some(&"pause"));
+
+        stack.pop(Transition::Cut);
+        stack.pop(Transition::Cut);
+        stack.pop(Transition::Cut);
+        assert_eq!(advance(&mut stack, 0.0), vec![(Hook::Exit, "pause"), (Hook::Resume, "level"), (Hook::Exit, "level")]);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn fades_switch_halfway() {
+        let mut stack = SceneStack::default();
+        stack.push("menu", Transition::Cut);
+        stack.replace("level", Transition::fade(0.5));
+        stack.push("pause", Transition::Cut);
+
+        assert_eq!(advance(&mut stack, 0.0), vec![(Hook::Enter, "menu")]);
+        assert_eq!(stack.fade_colour(), Some([0.0, 0.0, 0.0, 0.0]));
+
+        assert!(advance(&mut stack, 0.25).is_empty());
+        assert_eq!(stack.fade_colour(), Some([0.0, 0.0, 0.0, 0.5]));
+        assert_eq!(stack.top(), Some(&"menu"));
+
+        assert_eq!(advance(&mut stack, 0.25), vec![(Hook::Exit, "menu"), (Hook::Enter, "level")]);
+        assert_eq!(stack.fade_colour(), Some([0.0, 0.0, 0.0, 1.0]));
+
+        // The push waits for the fade in to finish.
+        assert!(advance(&mut stack, 0.25).is_empty());
+        assert!(stack.is_changing());
+        assert_eq!(advance(&mut stack, 0.25), vec![(Hook::Pause, "level"), (Hook::Enter, "pause")]);
+        assert_eq!(stack.fade_colour(), None);
+        assert!(!stack.is_changing());
+    }
+
+    #[test]
+    fn overlays_show_scenes_below() {
+        let mut stack = SceneStack::default();
+        stack.push(Scene::default(), Transition::Cut);
+        stack.push(Scene::default(), Transition::Cut);
+        stack.push(Scene { overlay: true, ..Scene::default() }, Transition::Cut);
+        stack.advance(0.0, |_, _| {});
+
+        assert_eq!(stack.visible_mut().len(), 2);
+        stack.push(Scene::default(), Transition::Cut);
+        stack.advance(0.0, |_, _| {});
+        assert_eq!(stack.visible_mut().len(), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use std::f32::consts::PI;
+
+/// Generated sounds are always at this rate.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Longest sound `generate` will make, in samples. The longest envelope is a bit under 7 seconds.
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize * 10;
+
+/// Each output sample is the average of this many.
+const SUPERSAMPLING: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+/// A retro sound effect, with the same parameters and ranges as sfxr so values from its tools carry over. Most are
+/// 0 to 1, the ramps and `arp_mod` are -1 to 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfxParams {
+    pub waveform: Waveform,
+
+    /// Envelope lengths.
+    pub attack: f32,
+    pub sustain: f32,
+    /// Extra volume at the start of the sustain, fading out.
+    pub punch: f32,
+    pub decay: f32,
+
+    /// Starting pitch.
+    pub base_freq: f32,
+    /// Stop the sound when the pitch slides down past this.
+    pub freq_limit: f32,
+    /// Pitch slide, and how quickly the slide itself changes.
+    pub freq_ramp: f32,
+    pub freq_delta_ramp: f32,
+
+    pub vibrato_depth: f32,
+    pub vibrato_speed: f32,
+
+    /// Jump in pitch partway through, e.g. the second note of a coin pickup. Positive goes up.
+    pub arp_mod: f32,
+    /// How soon the jump happens.
+    pub arp_speed: f32,
+
+    /// Square wave only.
+    pub duty: f32,
+    pub duty_ramp: f32,
+
+    /// Restart the pitch and arpeggio this often, 0 for never.
+    pub repeat_speed: f32,
+
+    pub phaser_offset: f32,
+    pub phaser_ramp: f32,
+
+    /// Low pass cutoff, 1 for none.
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub lpf_resonance: f32,
+    /// High pass cutoff, 0 for none.
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+
+    pub volume: f32,
+}
+
+impl Default for SfxParams {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            attack: 0.0,
+            sustain: 0.3,
+            punch: 0.0,
+            decay: 0.4,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_delta_ramp: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arp_mod: 0.0,
+            arp_speed: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            repeat_speed: 0.0,
+            phaser_offset: 0.0,
+            phaser_ramp: 0.0,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            volume: 0.5,
+        }
+    }
+}
+
+/// Small xorshift generator so presets are repeatable from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on zero, which the one seed equal to the constant would give.
+        match seed ^ 0x9e37_79b9_7f4a_7c15 {
+            0 => Rng(0x9e37_79b9_7f4a_7c15),
+            state => Rng(state),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// 0 to `range`.
+    fn float(&mut self, range: f32) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32 * range
+    }
+
+    /// 0 to `n` inclusive.
+    fn int(&mut self, n: u64) -> u64 {
+        self.next() % (n + 1)
+    }
+}
+
+impl SfxParams {
+    pub fn coin(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut params = Self {
+            base_freq: 0.4 + rng.float(0.5),
+            sustain: rng.float(0.1),
+            decay: 0.1 + rng.float(0.4),
+            punch: 0.3 + rng.float(0.3),
+            ..Self::default()
+        };
+        if rng.int(1) == 1 {
+            params.arp_speed = 0.5 + rng.float(0.2);
+            params.arp_mod = 0.2 + rng.float(0.4);
+        }
+        params
+    }
+
+    pub fn jump(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut params = Self {
+            duty: rng.float(0.6),
+            base_freq: 0.3 + rng.float(0.3),
+            freq_ramp: 0.1 + rng.float(0.2),
+            sustain: 0.1 + rng.float(0.3),
+            decay: 0.1 + rng.float(0.2),
+            ..Self::default()
+        };
+        if rng.int(1) == 1 {
+            params.hpf_freq = rng.float(0.3);
+        }
+        if rng.int(1) == 1 {
+            params.lpf_freq = 1.0 - rng.float(0.6);
+        }
+        params
+    }
+
+    pub fn explosion(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut params = Self { waveform: Waveform::Noise, ..Self::default() };
+        if rng.int(1) == 1 {
+            params.base_freq = 0.1 + rng.float(0.4);
+            params.freq_ramp = -0.1 + rng.float(0.4);
+        } else {
+            params.base_freq = 0.2 + rng.float(0.7);
+            params.freq_ramp = -0.2 - rng.float(0.2);
+        }
+        params.base_freq *= params.base_freq;
+        if rng.int(4) == 0 {
+            params.freq_ramp = 0.0;
+        }
+        if rng.int(2) == 0 {
+            params.repeat_speed = 0.3 + rng.float(0.5);
+        }
+        params.sustain = 0.1 + rng.float(0.3);
+        params.decay = rng.float(0.5);
+        if rng.int(1) == 0 {
+            params.phaser_offset = -0.3 + rng.float(0.9);
+            params.phaser_ramp = -rng.float(0.3);
+        }
+        params.punch = 0.2 + rng.float(0.6);
+        if rng.int(1) == 1 {
+            params.vibrato_depth = rng.float(0.7);
+            params.vibrato_speed = rng.float(0.6);
+        }
+        if rng.int(2) == 0 {
+            params.arp_speed = 0.6 + rng.float(0.3);
+            params.arp_mod = 0.8 - rng.float(1.6);
+        }
+        params
+    }
+
+    /// Render the sound as mono samples between -1 and 1 at `SAMPLE_RATE`.
+    pub fn generate(&self) -> Vec<f32> {
+        let mut synth = Synth::new(self);
+        let mut samples = Vec::new();
+        while let Some(sample) = synth.next_sample() {
+            samples.push(sample);
+            if samples.len() >= MAX_SAMPLES {
+                break;
+            }
+        }
+        samples
+    }
+}
+
+/// Running state while generating, after sfxr's `SynthSample`.
+struct Synth<'a> {
+    params: &'a SfxParams,
+    rng: Rng,
+
+    phase: usize,
+    period: f64,
+    max_period: f64,
+    slide: f64,
+    delta_slide: f64,
+    square_duty: f32,
+    square_slide: f32,
+
+    arp_mod: f64,
+    arp_time: usize,
+    arp_limit: usize,
+    repeat_time: usize,
+    repeat_limit: usize,
+
+    env_stage: usize,
+    env_time: usize,
+    env_lengths: [usize; 3],
+
+    vibrato_phase: f32,
+    vibrato_speed: f32,
+    vibrato_depth: f32,
+
+    lpf_pos: f32,
+    lpf_delta: f32,
+    lpf_cutoff: f32,
+    lpf_cutoff_ramp: f32,
+    lpf_damping: f32,
+    hpf_pos: f32,
+    hpf_cutoff: f32,
+    hpf_cutoff_ramp: f32,
+
+    phaser_phase: f32,
+    phaser_delta: f32,
+    phaser_pos: usize,
+    phaser_buffer: [f32; 1024],
+    noise_buffer: [f32; 32],
+}
+
+impl<'a> Synth<'a> {
+    fn new(params: &'a SfxParams) -> Self {
+        let squared = |x: f32| x * x;
+        let mut synth = Self {
+            params,
+            // Noise comes from a fixed seed so the same parameters always give the same sound.
+            rng: Rng::new(0),
+            phase: 0,
+            period: 0.0,
+            max_period: 0.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            arp_mod: 0.0,
+            arp_time: 0,
+            arp_limit: 0,
+            repeat_time: 0,
+            repeat_limit: 0,
+            env_stage: 0,
+            env_time: 0,
+            env_lengths: [
+                (squared(params.attack) * 100000.0) as usize,
+                (squared(params.sustain) * 100000.0) as usize,
+                (squared(params.decay) * 100000.0) as usize,
+            ],
+            vibrato_phase: 0.0,
+            vibrato_speed: squared(params.vibrato_speed) * 0.01,
+            vibrato_depth: params.vibrato_depth * 0.5,
+            lpf_pos: 0.0,
+            lpf_delta: 0.0,
+            lpf_cutoff: params.lpf_freq.powi(3) * 0.1,
+            lpf_cutoff_ramp: 1.0 + params.lpf_ramp * 0.0001,
+            lpf_damping: 0.0,
+            hpf_pos: 0.0,
+            hpf_cutoff: squared(params.hpf_freq) * 0.1,
+            hpf_cutoff_ramp: 1.0 + params.hpf_ramp * 0.0003,
+            phaser_phase: squared(params.phaser_offset) * 1020.0 * params.phaser_offset.signum(),
+            phaser_delta: squared(params.phaser_ramp) * params.phaser_ramp.signum(),
+            phaser_pos: 0,
+            phaser_buffer: [0.0; 1024],
+            noise_buffer: [0.0; 32],
+        };
+        synth.lpf_damping = (5.0 / (1.0 + squared(params.lpf_resonance) * 20.0) * (0.01 + synth.lpf_cutoff)).min(0.8);
+        if params.repeat_speed != 0.0 {
+            synth.repeat_limit = (squared(1.0 - params.repeat_speed) * 20000.0 + 32.0) as usize;
+        }
+        synth.fill_noise();
+        synth.restart();
+        synth
+    }
+
+    /// Reset pitch, duty and arpeggio, at the start and on every repeat.
+    fn restart(&mut self) {
+        let params = self.params;
+        let squared = |x: f64| x * x;
+        self.period = 100.0 / (squared(params.base_freq as f64) + 0.001);
+        self.max_period = 100.0 / (squared(params.freq_limit as f64) + 0.001);
+        self.slide = 1.0 - (params.freq_ramp as f64).powi(3) * 0.01;
+        self.delta_slide = -(params.freq_delta_ramp as f64).powi(3) * 0.000001;
+        self.square_duty = 0.5 - params.duty * 0.5;
+        self.square_slide = -params.duty_ramp * 0.00005;
+        self.arp_mod = if params.arp_mod >= 0.0 {
+            1.0 - squared(params.arp_mod as f64) * 0.9
+        } else {
+            1.0 + squared(params.arp_mod as f64) * 10.0
+        };
+        self.arp_time = 0;
+        self.arp_limit = if params.arp_speed == 1.0 {
+            0
+        } else {
+            (squared(1.0 - params.arp_speed as f64) * 20000.0 + 32.0) as usize
+        };
+    }
+
+    fn fill_noise(&mut self) {
+        for noise in self.noise_buffer.iter_mut() {
+            *noise = self.rng.float(2.0) - 1.0;
+        }
+    }
+
+    /// The next output sample, or `None` once the sound has finished.
+    fn next_sample(&mut self) -> Option<f32> {
+        let params = self.params;
+
+        self.repeat_time += 1;
+        if self.repeat_limit != 0 && self.repeat_time >= self.repeat_limit {
+            self.repeat_time = 0;
+            self.restart();
+        }
+
+        self.arp_time += 1;
+        if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+            self.arp_limit = 0;
+            self.period *= self.arp_mod;
+        }
+
+        self.slide += self.delta_slide;
+        self.period *= self.slide;
+        if self.period > self.max_period {
+            self.period = self.max_period;
+            if params.freq_limit > 0.0 {
+                return None;
+            }
+        }
+
+        let mut period = self.period as f32;
+        if self.vibrato_depth > 0.0 {
+            self.vibrato_phase += self.vibrato_speed;
+            period *= 1.0 + self.vibrato_phase.sin() * self.vibrato_depth;
+        }
+        let period = (period as usize).max(8);
+
+        self.square_duty = (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+        self.env_time += 1;
+        while self.env_time > self.env_lengths[self.env_stage] {
+            self.env_time = 0;
+            self.env_stage += 1;
+            if self.env_stage == 3 {
+                return None;
+            }
+        }
+        let progress = self.env_time as f32 / self.env_lengths[self.env_stage].max(1) as f32;
+        let envelope = match self.env_stage {
+            0 => progress,
+            1 => 1.0 + (1.0 - progress) * 2.0 * params.punch,
+            _ => 1.0 - progress,
+        };
+
+        self.phaser_phase += self.phaser_delta;
+        let phaser_offset = (self.phaser_phase.abs() as usize).min(1023);
+
+        if self.hpf_cutoff_ramp != 1.0 {
+            self.hpf_cutoff = (self.hpf_cutoff * self.hpf_cutoff_ramp).clamp(0.00001, 0.1);
+        }
+
+        let mut total = 0.0;
+        for _ in 0..SUPERSAMPLING {
+            self.phase += 1;
+            if self.phase >= period {
+                self.phase %= period;
+                if params.waveform == Waveform::Noise {
+                    self.fill_noise();
+                }
+            }
+
+            let position = self.phase as f32 / period as f32;
+            let mut sample = match params.waveform {
+                Waveform::Square => if position < self.square_duty { 0.5 } else { -0.5 },
+                Waveform::Sawtooth => 1.0 - position * 2.0,
+                Waveform::Sine => (position * 2.0 * PI).sin(),
+                Waveform::Noise => self.noise_buffer[self.phase * 32 / period],
+            };
+
+            let previous = self.lpf_pos;
+            self.lpf_cutoff = (self.lpf_cutoff * self.lpf_cutoff_ramp).clamp(0.0, 0.1);
+            if params.lpf_freq != 1.0 {
+                self.lpf_delta += (sample - self.lpf_pos) * self.lpf_cutoff;
+                self.lpf_delta -= self.lpf_delta * self.lpf_damping;
+            } else {
+                self.lpf_pos = sample;
+                self.lpf_delta = 0.0;
+            }
+            self.lpf_pos += self.lpf_delta;
+
+            self.hpf_pos += self.lpf_pos - previous;
+            self.hpf_pos -= self.hpf_pos * self.hpf_cutoff;
+            sample = self.hpf_pos;
+
+            self.phaser_buffer[self.phaser_pos & 1023] = sample;
+            sample += self.phaser_buffer[(self.phaser_pos + 1024 - phaser_offset) & 1023];
+            self.phaser_pos = (self.phaser_pos + 1) & 1023;
+
+            total += sample * envelope;
+        }
+
+        // sfxr's master volume, then the extra gain it adds when exporting to get a reasonable level.
+        let sample = total / SUPERSAMPLING as f32 * 0.05 * 2.0 * params.volume * 4.0;
+        Some(sample.clamp(-1.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sfxr::{Rng, SAMPLE_RATE, SfxParams, Waveform};
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn no_seed_gets_stuck_on_zero() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        assert_ne!(rng.next(), 0);
+    }
+
+    #[test]
+    fn length_follows_the_envelope() {
+        let params = SfxParams { attack: 0.1, sustain: 0.2, decay: 0.3, ..SfxParams::default() };
+        let samples = params.generate();
+        let expected = 1000 + 4000 + 9000;
+        assert!((samples.len() as i32 - expected).abs() <= 3, "{} samples", samples.len());
+    }
+
+    #[test]
+    fn sine_pitch_matches_base_freq() {
+        let params = SfxParams { waveform: Waveform::Sine, sustain: 1.0, decay: 0.0, ..SfxParams::default() };
+        let samples = params.generate();
+        let second = &samples[..SAMPLE_RATE as usize];
+        let crossings = second.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count();
+
+        // A period of 100 / (0.3² + 0.001) supersamples, 8 to a sample.
+        let expected = SAMPLE_RATE as f64 * 8.0 / (100.0 / (0.09 + 0.001)) * 2.0;
+        assert!((crossings as f64 - expected).abs() < 10.0, "{} crossings, expected {}", crossings, expected);
+    }
+
+    #[test]
+    fn attack_fades_in_and_decay_fades_out() {
+        let params = SfxParams { attack: 0.2, sustain: 0.1, decay: 0.2, ..SfxParams::default() };
+        let samples = params.generate();
+        let (start, end) = (&samples[..50], &samples[samples.len() - 50..]);
+        assert!(peak(start) < 0.01 && peak(end) < 0.01);
+        assert!(peak(&samples) > 0.1);
+    }
+
+    #[test]
+    fn freq_limit_ends_the_sound_early() {
+        let sliding = SfxParams { freq_ramp: -0.5, sustain: 1.0, ..SfxParams::default() };
+        let limited = SfxParams { freq_limit: 0.2, ..sliding.clone() };
+        assert!(limited.generate().len() < sliding.generate().len());
+    }
+
+    #[test]
+    fn presets_are_repeatable_and_audible() {
+        for preset in [SfxParams::coin, SfxParams::jump, SfxParams::explosion].iter() {
+            assert_eq!(preset(7), preset(7));
+            assert_ne!(preset(7), preset(8));
+
+            let samples = preset(7).generate();
+            assert!(!samples.is_empty());
+            assert!(peak(&samples) > 0.05);
+            assert!(samples.iter().all(|sample| sample.is_finite() && sample.abs() <= 1.0));
+        }
+        assert_eq!(SfxParams::explosion(3).waveform, Waveform::Noise);
+    }
+}
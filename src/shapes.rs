@@ -0,0 +1,131 @@
+/// How the inside of a closed shape is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fill {
+    Solid,
+    /// Just the edge, this many pixels thick. The line is centred on the edge.
+    Outline(f32),
+}
+
+/// Untextured geometry, drawn with `LagomGame::draw_shape`. Coordinates are local to the `DrawParams` position and
+/// are moved, rotated and scaled by it the same way a sprite is, `tint` sets the colour.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Line { from: (f32, f32), to: (f32, f32), thickness: f32 },
+    /// Top left corner at (0, 0).
+    Rect { width: f32, height: f32, fill: Fill },
+    /// Centred on (0, 0).
+    Circle { radius: f32, fill: Fill },
+    /// Points in order around the edge. Filling only works for convex polygons.
+    Polygon { points: Vec<(f32, f32)>, fill: Fill },
+}
+
+impl Shape {
+    /// Break the shape down into a list of triangles, three points each.
+    pub fn triangles(&self) -> Vec<(f32, f32)> {
+        match self {
+            Shape::Line { from, to, thickness } => line(*from, *to, *thickness),
+            Shape::Rect { width, height, fill } => {
+                let corners = [(0.0, 0.0), (*width, 0.0), (*width, *height), (0.0, *height)];
+                polygon(&corners, *fill)
+            }
+            Shape::Circle { radius, fill } => polygon(&circle_points(*radius), *fill),
+            Shape::Polygon { points, fill } => polygon(points, *fill),
+        }
+    }
+}
+
+fn line(from: (f32, f32), to: (f32, f32), thickness: f32) -> Vec<(f32, f32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return Vec::new();
+    }
+
+    // Perpendicular offset, half the thickness each side.
+    let (nx, ny) = (-dy / length * thickness / 2.0, dx / length * thickness / 2.0);
+
+    let a = (from.0 + nx, from.1 + ny);
+    let b = (from.0 - nx, from.1 - ny);
+    let c = (to.0 + nx, to.1 + ny);
+    let d = (to.0 - nx, to.1 - ny);
+    vec![a, b, c, c, b, d]
+}
+
+fn polygon(points: &[(f32, f32)], fill: Fill) -> Vec<(f32, f32)> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    match fill {
+        Fill::Solid => {
+            // Triangle fan from the first point.
+            let mut triangles = Vec::with_capacity((points.len() - 2) * 3);
+            for i in 1..points.len() - 1 {
+                triangles.extend_from_slice(&[points[0], points[i], points[i + 1]]);
+            }
+            triangles
+        }
+        Fill::Outline(thickness) => {
+            let mut triangles = Vec::with_capacity(points.len() * 6);
+            for i in 0..points.len() {
+                triangles.extend(line(points[i], points[(i + 1) % points.len()], thickness));
+            }
+            triangles
+        }
+    }
+}
+
+fn circle_points(radius: f32) -> Vec<(f32, f32)> {
+    // Bigger circles need more segments to look round.
+    let segments = ((radius.max(1.0).sqrt() * 6.0) as usize).clamp(8, 96);
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::PI * 2.0;
+            (angle.cos() * radius, angle.sin() * radius)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::shapes::{Fill, Shape};
+
+    #[test]
+    fn filled_rect_is_two_triangles() {
+        let triangles = Shape::Rect { width: 4.0, height: 2.0, fill: Fill::Solid }.triangles();
+        assert_eq!(triangles, vec![(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (0.0, 0.0), (4.0, 2.0), (0.0, 2.0)]);
+    }
+
+    #[test]
+    fn outlined_rect_is_four_lines() {
+        let triangles = Shape::Rect { width: 4.0, height: 2.0, fill: Fill::Outline(1.0) }.triangles();
+        assert_eq!(triangles.len(), 4 * 6);
+    }
+
+    #[test]
+    fn line_has_thickness() {
+        let triangles = Shape::Line { from: (0.0, 0.0), to: (10.0, 0.0), thickness: 2.0 }.triangles();
+        assert_eq!(triangles, vec![(0.0, 1.0), (0.0, -1.0), (10.0, 1.0), (10.0, 1.0), (0.0, -1.0), (10.0, -1.0)]);
+    }
+
+    #[test]
+    fn zero_length_line_is_empty() {
+        assert!(Shape::Line { from: (3.0, 3.0), to: (3.0, 3.0), thickness: 2.0 }.triangles().is_empty());
+    }
+
+    #[test]
+    fn circle_points_are_on_the_radius() {
+        let triangles = Shape::Circle { radius: 50.0, fill: Fill::Solid }.triangles();
+        assert_eq!(triangles.len() % 3, 0);
+        for (x, y) in triangles {
+            assert!(((x * x + y * y).sqrt() - 50.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn polygon_fan() {
+        let points = vec![(0.0, 0.0), (2.0, 0.0), (3.0, 1.0), (1.0, 2.0), (-1.0, 1.0)];
+        let triangles = Shape::Polygon { points, fill: Fill::Solid }.triangles();
+        assert_eq!(triangles.len(), 3 * 3);
+    }
+}
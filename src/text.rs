@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+
+use crate::error::LagomError;
+use crate::ttf::TrueTypeFont;
+
+pub type FontId = usize;
+
+/// Where a character lives in a font texture and how to place it, all in pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub texture: u32,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    pub x_advance: f32,
+}
+
+/// Anything text can be laid out with.
+pub trait FontMetrics {
+    fn glyph(&self, c: char) -> Option<&Glyph>;
+
+    /// Extra space to add between two characters, usually negative.
+    fn kerning(&self, first: char, second: char) -> f32;
+
+    fn line_height(&self) -> f32;
+}
+
+/// A font drawn from one or more textures, loaded from an AngelCode BMFont file or a monospace grid.
+#[derive(Debug, Default)]
+pub struct BitmapFont {
+    pub line_height: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+impl FontMetrics for BitmapFont {
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c)
+    }
+
+    fn kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).cloned().unwrap_or(0.0)
+    }
+
+    fn line_height(&self) -> f32 {
+        self.line_height
+    }
+}
+
+/// Any kind of font `LagomGame` can draw text with.
+pub enum Font {
+    Bitmap(BitmapFont),
+    TrueType(TrueTypeFont),
+}
+
+impl FontMetrics for Font {
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        match self {
+            Font::Bitmap(font) => font.glyph(c),
+            Font::TrueType(font) => font.glyph(c),
+        }
+    }
+
+    fn kerning(&self, first: char, second: char) -> f32 {
+        match self {
+            Font::Bitmap(font) => font.kerning(first, second),
+            Font::TrueType(font) => font.kerning(first, second),
+        }
+    }
+
+    fn line_height(&self) -> f32 {
+        match self {
+            Font::Bitmap(font) => font.line_height(),
+            Font::TrueType(font) => font.line_height(),
+        }
+    }
+}
+
+/// Pull the `key=value` pairs out of a BMFont line. Quoted values keep their spaces.
+fn bmfont_attributes(line: &str) -> HashMap<&str, &str> {
+    let mut attributes = HashMap::new();
+    let mut rest = line;
+
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].rsplit(' ').next().unwrap_or("");
+        let after = &rest[eq + 1..];
+
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], &quoted[(end + 1).min(quoted.len())..])
+        } else {
+            let end = after.find(' ').unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        attributes.insert(key, value);
+        rest = remaining;
+    }
+
+    attributes
+}
+
+impl BitmapFont {
+    /// Parse the text version of an AngelCode BMFont `.fnt` file. `pages` are the texture IDs for each page, in order.
+    pub fn from_bmfont(source: &str, pages: &[u32]) -> Result<Self, LagomError> {
+        let mut font = BitmapFont::default();
+
+        for line in source.lines() {
+            let line = line.trim();
+            let tag = line.split(' ').next().unwrap_or("");
+            let attributes = bmfont_attributes(line);
+
+            let number = |key: &str| -> Result<f32, LagomError> {
+                attributes.get(key)
+                    .ok_or_else(|| LagomError::AssetDecode(format!("BMFont {} line is missing {}", tag, key)))?
+                    .parse::<f32>()
+                    .map_err(|e| LagomError::AssetDecode(format!("BMFont {} has a bad {}: {}", tag, key, e)))
+            };
+            let character = |key: &str| -> Result<char, LagomError> {
+                std::char::from_u32(number(key)? as u32)
+                    .ok_or_else(|| LagomError::AssetDecode(format!("BMFont {} is not a character", key)))
+            };
+
+            match tag {
+                "common" => font.line_height = number("lineHeight")?,
+                "char" => {
+                    let page = number("page").unwrap_or(0.0) as usize;
+                    let texture = *pages.get(page)
+                        .ok_or_else(|| LagomError::AssetDecode(format!("BMFont uses page {} but it wasn't given", page)))?;
+
+                    font.glyphs.insert(character("id")?, Glyph {
+                        texture,
+                        x: number("x")?,
+                        y: number("y")?,
+                        width: number("width")?,
+                        height: number("height")?,
+                        x_offset: number("xoffset")?,
+                        y_offset: number("yoffset")?,
+                        x_advance: number("xadvance")?,
+                    });
+                }
+                "kerning" => {
+                    font.kerning.insert((character("first")?, character("second")?), number("amount")?);
+                }
+                _ => {}
+            }
+        }
+
+        if font.glyphs.is_empty() {
+            return Err(LagomError::AssetDecode(String::from("BMFont file has no characters")));
+        }
+        Ok(font)
+    }
+
+    /// A fixed width font laid out as a grid, left to right then top to bottom, in the order of `characters`.
+    pub fn monospace(texture: u32, texture_width: u32, glyph_width: u32, glyph_height: u32, characters: &str)
+                     -> Result<Self, LagomError> {
+        if glyph_width == 0 || glyph_height == 0 {
+            return Err(LagomError::AssetDecode(format!("glyphs can't be {}x{}", glyph_width, glyph_height)));
+        }
+        let columns = (texture_width / glyph_width).max(1);
+
+        let glyphs = characters.chars().enumerate().map(|(i, c)| {
+            let (column, row) = (i as u32 % columns, i as u32 / columns);
+            (c, Glyph {
+                texture,
+                x: (column * glyph_width) as f32,
+                y: (row * glyph_height) as f32,
+                width: glyph_width as f32,
+                height: glyph_height as f32,
+                x_offset: 0.0,
+                y_offset: 0.0,
+                x_advance: glyph_width as f32,
+            })
+        }).collect();
+
+        Ok(Self { line_height: glyph_height as f32, glyphs, kerning: HashMap::new() })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    /// Wrap lines at spaces so they fit in this many pixels. Words longer than this are left whole.
+    pub max_width: Option<f32>,
+    /// Lines are aligned within `max_width`, or the widest line if there isn't one.
+    pub align: Align,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self { max_width: None, align: Align::Left }
+    }
+}
+
+/// A glyph positioned relative to the top left of the text block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedGlyph {
+    pub texture: u32,
+    pub source: (f32, f32, f32, f32),
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Fallback for characters the font doesn't have.
+const MISSING_GLYPH: char = '?';
+
+fn glyph_for<F: FontMetrics>(font: &F, c: char) -> Option<&Glyph> {
+    font.glyph(c).or_else(|| font.glyph(MISSING_GLYPH))
+}
+
+fn measure<F: FontMetrics>(font: &F, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        if let Some(prev) = previous {
+            width += font.kerning(prev, c);
+        }
+        width += glyph_for(font, c).map(|glyph| glyph.x_advance).unwrap_or(0.0);
+        previous = Some(c);
+    }
+    width
+}
+
+/// Split text into lines, honouring newlines and wrapping at spaces.
+fn wrap<F: FontMetrics>(font: &F, text: &str, max_width: Option<f32>) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let max_width = match max_width {
+            Some(max_width) => max_width,
+            None => {
+                lines.push(paragraph.to_string());
+                continue;
+            }
+        };
+
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if !line.is_empty() && measure(font, &candidate) > max_width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Work out where every glyph of `text` goes.
+pub fn layout<F: FontMetrics>(font: &F, text: &str, style: &TextStyle) -> Vec<PlacedGlyph> {
+    let lines = wrap(font, text, style.max_width);
+    let widths: Vec<f32> = lines.iter().map(|line| measure(font, line)).collect();
+    let box_width = style.max_width.unwrap_or_else(|| widths.iter().cloned().fold(0.0, f32::max));
+
+    let mut placed = Vec::new();
+    for (row, (line, width)) in lines.iter().zip(widths).enumerate() {
+        let mut pen = match style.align {
+            Align::Left => 0.0,
+            Align::Center => ((box_width - width) / 2.0).floor(),
+            Align::Right => box_width - width,
+        };
+        let y = row as f32 * font.line_height();
+
+        let mut previous = None;
+        for c in line.chars() {
+            if let Some(prev) = previous {
+                pen += font.kerning(prev, c);
+            }
+            previous = Some(c);
+
+            let glyph = match glyph_for(font, c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+
+            if !c.is_whitespace() && glyph.width > 0.0 && glyph.height > 0.0 {
+                placed.push(PlacedGlyph {
+                    texture: glyph.texture,
+                    source: (glyph.x, glyph.y, glyph.width, glyph.height),
+                    x: pen + glyph.x_offset,
+                    y: y + glyph.y_offset,
+                });
+            }
+            pen += glyph.x_advance;
+        }
+    }
+
+    placed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text::{Align, BitmapFont, FontMetrics, layout, TextStyle};
+
+    const FNT: &str = r#"info face="Test Font" size=16 bold=0 italic=0 charset="" unicode=1 stretchH=100 smooth=0 aa=1 padding=0,0,0,0 spacing=1,1
+common lineHeight=18 base=14 scaleW=128 scaleH=128 pages=2 packed=0
+page id=0 file="test_0.png"
+page id=1 file="test_1.png"
+chars count=4
+char id=32   x=0     y=0     width=0     height=0     xoffset=0     yoffset=0     xadvance=4     page=0  chnl=15
+char id=65   x=10    y=20    width=8     height=10    xoffset=1     yoffset=2     xadvance=9     page=0  chnl=15
+char id=86   x=20    y=20    width=8     height=10    xoffset=0     yoffset=2     xadvance=9     page=1  chnl=15
+char id=63   x=30    y=20    width=6     height=10    xoffset=0     yoffset=2     xadvance=7     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-2
+"#;
+
+    #[test]
+    fn parse_bmfont() {
+        let font = BitmapFont::from_bmfont(FNT, &[5, 6]).unwrap();
+
+        assert_eq!(font.line_height(), 18.0);
+        let a = font.glyph('A').unwrap();
+        assert_eq!((a.texture, a.x, a.y, a.x_offset, a.x_advance), (5, 10.0, 20.0, 1.0, 9.0));
+        assert_eq!(font.glyph('V').unwrap().texture, 6);
+        assert_eq!(font.kerning('A', 'V'), -2.0);
+        assert_eq!(font.kerning('V', 'A'), 0.0);
+    }
+
+    #[test]
+    fn bmfont_missing_page() {
+        assert!(BitmapFont::from_bmfont(FNT, &[5]).is_err());
+    }
+
+    #[test]
+    fn monospace_grid() {
+        let font = BitmapFont::monospace(0, 32, 8, 8, "ABCDEF").unwrap();
+        let f = font.glyph('F').unwrap();
+        assert_eq!((f.x, f.y, f.width, f.x_advance), (8.0, 8.0, 8.0, 8.0));
+    }
+
+    #[test]
+    fn monospace_needs_a_glyph_size() {
+        assert!(BitmapFont::monospace(0, 32, 0, 8, "AB").is_err());
+        assert!(BitmapFont::monospace(0, 32, 8, 0, "AB").is_err());
+    }
+
+    #[test]
+    fn layout_applies_kerning_and_offsets() {
+        let font = BitmapFont::from_bmfont(FNT, &[0, 1]).unwrap();
+        let glyphs = layout(&font, "AV", &TextStyle::default());
+
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!((glyphs[0].x, glyphs[0].y), (1.0, 2.0));
+        // 9 advance - 2 kerning.
+        assert_eq!((glyphs[1].x, glyphs[1].y), (7.0, 2.0));
+    }
+
+    #[test]
+    fn unknown_characters_fall_back() {
+        let font = BitmapFont::from_bmfont(FNT, &[0, 1]).unwrap();
+        let glyphs = layout(&font, "Z", &TextStyle::default());
+        assert_eq!(glyphs[0].source, (30.0, 20.0, 6.0, 10.0));
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let font = BitmapFont::monospace(0, 64, 8, 8, "ABC ").unwrap();
+        let style = TextStyle { max_width: Some(40.0), align: Align::Left };
+        let glyphs = layout(&font, "AB CC A\nB", &style);
+
+        let rows: Vec<(f32, f32)> = glyphs.iter().map(|g| (g.x, g.y)).collect();
+        assert_eq!(rows, vec![
+            (0.0, 0.0), (8.0, 0.0), (24.0, 0.0), (32.0, 0.0),
+            (0.0, 8.0),
+            (0.0, 16.0),
+        ]);
+    }
+
+    #[test]
+    fn alignment() {
+        let font = BitmapFont::monospace(0, 64, 8, 8, "AB").unwrap();
+        let right = TextStyle { max_width: Some(40.0), align: Align::Right };
+        assert_eq!(layout(&font, "AB", &right)[0].x, 24.0);
+
+        // Without a width lines are aligned against the widest one.
+        let center = TextStyle { max_width: None, align: Align::Center };
+        let glyphs = layout(&font, "AAAA\nBB", &center);
+        assert_eq!((glyphs[4].x, glyphs[4].y), (8.0, 8.0));
+    }
+}
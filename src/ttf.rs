@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont, point};
+use image::{GenericImage, Rgba, RgbaImage};
+
+use crate::error::LagomError;
+use crate::text::{FontMetrics, Glyph};
+
+/// Space left between glyphs so filtering doesn't pick up the neighbours.
+const PADDING: u32 = 1;
+
+/// A square texture glyphs are packed into row by row. Doubles in size when it runs out of room, which leaves the
+/// existing glyphs where they were.
+pub struct GlyphAtlas {
+    image: RgbaImage,
+    cursor_x: u32,
+    cursor_y: u32,
+    row_height: u32,
+    max_size: u32,
+}
+
+impl GlyphAtlas {
+    pub fn new(size: u32, max_size: u32) -> Self {
+        Self { image: RgbaImage::new(size, size), cursor_x: 0, cursor_y: 0, row_height: 0, max_size }
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Find space for a `width` x `height` glyph, returning its top left corner. `None` if it can't fit even after
+    /// growing to the maximum size.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        loop {
+            let size = self.image.width();
+
+            if self.cursor_x + width > size {
+                // Start a new row.
+                self.cursor_x = 0;
+                self.cursor_y += self.row_height + PADDING;
+                self.row_height = 0;
+            }
+
+            if self.cursor_x + width <= size && self.cursor_y + height <= size {
+                let position = (self.cursor_x, self.cursor_y);
+                self.cursor_x += width + PADDING;
+                self.row_height = self.row_height.max(height);
+                return Some(position);
+            }
+
+            if size * 2 > self.max_size {
+                return None;
+            }
+            self.grow(size * 2);
+        }
+    }
+
+    fn grow(&mut self, size: u32) {
+        let mut image = RgbaImage::new(size, size);
+        image.copy_from(&self.image, 0, 0).expect("atlas grew smaller");
+        self.image = image;
+
+        // The rows so far still end at the old edge, so carry on from the next one.
+        if self.cursor_x > 0 {
+            self.cursor_x = 0;
+            self.cursor_y += self.row_height + PADDING;
+            self.row_height = 0;
+        }
+    }
+
+    pub fn image_mut(&mut self) -> &mut RgbaImage {
+        &mut self.image
+    }
+}
+
+/// A TrueType or OpenType font at one pixel size. Glyphs are rasterized into the atlas the first time they are used.
+pub struct TrueTypeFont {
+    font: FontArc,
+    size: f32,
+    texture: u32,
+    atlas: GlyphAtlas,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl TrueTypeFont {
+    pub fn parse(bytes: Vec<u8>) -> Result<FontArc, LagomError> {
+        FontArc::try_from_vec(bytes).map_err(|e| LagomError::AssetDecode(format!("Couldn't read font: {}", e)))
+    }
+
+    /// `texture` is the ID the atlas image is uploaded to.
+    pub fn new(font: FontArc, size: f32, texture: u32, atlas: GlyphAtlas) -> Self {
+        Self { font, size, texture, atlas, glyphs: HashMap::new() }
+    }
+
+    /// The parsed font, for loading it again at another size without parsing it twice.
+    pub fn font(&self) -> &FontArc {
+        &self.font
+    }
+
+    /// Use a new version of the font at the same size, e.g. after the file changed. Glyphs are rasterized again into
+    /// the fresh `atlas` as they're drawn.
+    pub fn replace_font(&mut self, font: FontArc, atlas: GlyphAtlas) {
+        self.font = font;
+        self.atlas = atlas;
+        self.glyphs.clear();
+    }
+
+    pub fn texture(&self) -> u32 {
+        self.texture
+    }
+
+    pub fn atlas_image(&self) -> &RgbaImage {
+        self.atlas.image()
+    }
+
+    /// Rasterize any characters of `text` that aren't in the atlas yet. Returns true if the atlas changed and needs
+    /// uploading again.
+    pub fn prepare(&mut self, text: &str) -> bool {
+        let mut changed = false;
+        for c in text.chars() {
+            if !self.glyphs.contains_key(&c) {
+                changed |= self.rasterize(c);
+            }
+        }
+        changed
+    }
+
+    /// Returns true if anything was drawn into the atlas, spaces and the like only need their advance.
+    fn rasterize(&mut self, c: char) -> bool {
+        let scaled = self.font.as_scaled(PxScale::from(self.size));
+        let id = self.font.glyph_id(c);
+        let x_advance = scaled.h_advance(id);
+
+        // Place the glyph with the top of the line at 0 so the bounds are offsets from the top left of the line.
+        let positioned = id.with_scale_and_position(self.size, point(0.0, scaled.ascent()));
+
+        let mut drawn = false;
+        let mut glyph = Glyph {
+            texture: self.texture,
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            x_advance,
+        };
+
+        if let Some(outlined) = self.font.outline_glyph(positioned) {
+            let bounds = outlined.px_bounds();
+            let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+
+            if let Some((x, y)) = self.atlas.allocate(width, height) {
+                let image = self.atlas.image_mut();
+                outlined.draw(|gx, gy, coverage| {
+                    image.put_pixel(x + gx, y + gy, Rgba([255, 255, 255, (coverage * 255.0) as u8]));
+                });
+
+                glyph = Glyph {
+                    x: x as f32,
+                    y: y as f32,
+                    width: width as f32,
+                    height: height as f32,
+                    x_offset: bounds.min.x,
+                    y_offset: bounds.min.y,
+                    ..glyph
+                };
+                drawn = true;
+            }
+        }
+
+        self.glyphs.insert(c, glyph);
+        drawn
+    }
+}
+
+impl FontMetrics for TrueTypeFont {
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c)
+    }
+
+    fn kerning(&self, first: char, second: char) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(self.size));
+        scaled.kern(self.font.glyph_id(first), self.font.glyph_id(second))
+    }
+
+    fn line_height(&self) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(self.size));
+        scaled.height() + scaled.line_gap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ttf::{GlyphAtlas, TrueTypeFont};
+
+    #[test]
+    fn atlas_packs_rows() {
+        let mut atlas = GlyphAtlas::new(16, 64);
+        assert_eq!(atlas.allocate(6, 4), Some((0, 0)));
+        assert_eq!(atlas.allocate(6, 5), Some((7, 0)));
+        // Doesn't fit on the first row, goes under the tallest glyph.
+        assert_eq!(atlas.allocate(6, 3), Some((0, 6)));
+    }
+
+    #[test]
+    fn atlas_grows_and_keeps_pixels() {
+        let mut atlas = GlyphAtlas::new(8, 64);
+        let (x, y) = atlas.allocate(8, 8).unwrap();
+        atlas.image_mut().put_pixel(x + 3, y + 3, image::Rgba([1, 2, 3, 4]));
+
+        let next = atlas.allocate(8, 8).unwrap();
+        assert!(atlas.image().width() > 8);
+        assert_eq!(next, (0, 9));
+        assert_eq!(atlas.image().get_pixel(3, 3).0, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn atlas_has_a_limit() {
+        let mut atlas = GlyphAtlas::new(8, 16);
+        assert_eq!(atlas.allocate(32, 32), None);
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert!(TrueTypeFont::parse(vec![0, 1, 2, 3]).is_err());
+    }
+}